@@ -0,0 +1,400 @@
+use super::*;
+use san::SanMove;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Score of being checkmated, in centipawns. Mates found sooner score further from zero.
+const MATE: i32 = 100_000;
+
+/// Piece-square tables in centipawns, seen from white with rank 8 first like Game::board.
+/// Black pieces use the tables mirrored vertically.
+const PAWN_TABLE: [[i32; 8]; 8] = [
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [50, 50, 50, 50, 50, 50, 50, 50],
+    [10, 10, 20, 30, 30, 20, 10, 10],
+    [5, 5, 10, 25, 25, 10, 5, 5],
+    [0, 0, 0, 20, 20, 0, 0, 0],
+    [5, -5, -10, 0, 0, -10, -5, 5],
+    [5, 10, 10, -20, -20, 10, 10, 5],
+    [0, 0, 0, 0, 0, 0, 0, 0],
+];
+const KNIGHT_TABLE: [[i32; 8]; 8] = [
+    [-50, -40, -30, -30, -30, -30, -40, -50],
+    [-40, -20, 0, 0, 0, 0, -20, -40],
+    [-30, 0, 10, 15, 15, 10, 0, -30],
+    [-30, 5, 15, 20, 20, 15, 5, -30],
+    [-30, 0, 15, 20, 20, 15, 0, -30],
+    [-30, 5, 10, 15, 15, 10, 5, -30],
+    [-40, -20, 0, 5, 5, 0, -20, -40],
+    [-50, -40, -30, -30, -30, -30, -40, -50],
+];
+const BISHOP_TABLE: [[i32; 8]; 8] = [
+    [-20, -10, -10, -10, -10, -10, -10, -20],
+    [-10, 0, 0, 0, 0, 0, 0, -10],
+    [-10, 0, 5, 10, 10, 5, 0, -10],
+    [-10, 5, 5, 10, 10, 5, 5, -10],
+    [-10, 0, 10, 10, 10, 10, 0, -10],
+    [-10, 10, 10, 10, 10, 10, 10, -10],
+    [-10, 5, 0, 0, 0, 0, 5, -10],
+    [-20, -10, -10, -10, -10, -10, -10, -20],
+];
+const ROOK_TABLE: [[i32; 8]; 8] = [
+    [0, 0, 0, 0, 0, 0, 0, 0],
+    [5, 10, 10, 10, 10, 10, 10, 5],
+    [-5, 0, 0, 0, 0, 0, 0, -5],
+    [-5, 0, 0, 0, 0, 0, 0, -5],
+    [-5, 0, 0, 0, 0, 0, 0, -5],
+    [-5, 0, 0, 0, 0, 0, 0, -5],
+    [-5, 0, 0, 0, 0, 0, 0, -5],
+    [0, 0, 0, 5, 5, 0, 0, 0],
+];
+const QUEEN_TABLE: [[i32; 8]; 8] = [
+    [-20, -10, -10, -5, -5, -10, -10, -20],
+    [-10, 0, 0, 0, 0, 0, 0, -10],
+    [-10, 0, 5, 5, 5, 5, 0, -10],
+    [-5, 0, 5, 5, 5, 5, 0, -5],
+    [0, 0, 5, 5, 5, 5, 0, -5],
+    [-10, 5, 5, 5, 5, 5, 0, -10],
+    [-10, 0, 5, 0, 0, 0, 0, -10],
+    [-20, -10, -10, -5, -5, -10, -10, -20],
+];
+const KING_TABLE: [[i32; 8]; 8] = [
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-30, -40, -40, -50, -50, -40, -40, -30],
+    [-20, -30, -30, -40, -40, -30, -30, -20],
+    [-10, -20, -20, -20, -20, -20, -20, -10],
+    [20, 20, 0, 0, 0, 0, 20, 20],
+    [20, 30, 10, 0, 0, 10, 30, 20],
+];
+
+/// ## Level
+/// How strong the computer opponent plays.
+/// Weaker levels search less deeply and make random mistakes.
+#[derive(Clone, Copy)]
+pub struct Level {
+    pub name: &'static str,
+    pub depth: usize, // Maximum search depth in plies
+    pub time: Duration, // Deeper searches are abandoned once this time has passed
+    pub noise: i32, // Largest random number of centipawns added to the score of each move
+    pub blunders: u64, // Percentage of moves picked at random
+}
+
+/// The levels of the computer opponent from weakest to strongest.
+pub const LEVELS: [Level; 4] = [
+    Level {
+        name: "Beginner",
+        depth: 1,
+        time: Duration::from_secs(1),
+        noise: 150,
+        blunders: 20,
+    },
+    Level {
+        name: "Easy",
+        depth: 2,
+        time: Duration::from_secs(2),
+        noise: 60,
+        blunders: 8,
+    },
+    Level {
+        name: "Medium",
+        depth: 3,
+        time: Duration::from_secs(3),
+        noise: 20,
+        blunders: 0,
+    },
+    Level {
+        name: "Hard",
+        depth: 5,
+        time: Duration::from_secs(5),
+        noise: 0,
+        blunders: 0,
+    },
+];
+
+/// ## Search
+/// A search for the best move running on a background thread.
+/// Dropping the search cancels it.
+pub struct Search {
+    result: mpsc::Receiver<SanMove>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Search {
+    /// Starts searching the position given by a FEN string at the given level.
+    pub fn start(fen: String, level: Level) -> Search {
+        let (sender, result) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let cancel = Arc::clone(&cancelled);
+        thread::spawn(move || {
+            if let Some(found) = best_move(&fen, level, &cancel) {
+                let _ = sender.send(found);
+            }
+        });
+        Search { result, cancelled }
+    }
+
+    /// The move found, once the search is finished.
+    pub fn poll(&self) -> Option<SanMove> {
+        self.result.try_recv().ok()
+    }
+}
+
+impl Drop for Search {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// When a search has to give up.
+struct Limits<'a> {
+    deadline: Option<Instant>,
+    cancelled: &'a AtomicBool,
+}
+
+impl Limits<'_> {
+    fn exceeded(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.deadline.is_some_and(|deadline| Instant::now() > deadline)
+    }
+}
+
+/// Finds the move to play in the position given by a FEN string, None if there are no legal moves.
+/// Searches one ply deeper at a time, keeping the result of the last search finished in time.
+fn best_move(fen: &str, level: Level, cancelled: &AtomicBool) -> Option<SanMove> {
+    let game = game_from_fen(fen);
+    let mut moves = ordered_moves(&game);
+    let mut random = Random::seeded();
+    if moves.is_empty() {
+        return None;
+    }
+    if random.below(100) < level.blunders {
+        let (from, to) = moves[random.below(moves.len() as u64) as usize];
+        return Some((from, to, Some('q')));
+    }
+
+    let limits = Limits {
+        deadline: Some(Instant::now() + level.time),
+        cancelled,
+    };
+    for depth in 1..=level.depth {
+        // With noise every move needs an exact score, otherwise moves only need to beat the best so far
+        let mut scored = Vec::new();
+        let mut alpha = -MATE - 1;
+        for m in &moves {
+            let window = if level.noise > 0 { -MATE - 1 } else { alpha };
+            let score = match search(&play(&game, *m), depth - 1, -MATE - 1, -window, 1, &limits) {
+                Some(score) => -score + random.noise(level.noise),
+                None => return Some((moves[0].0, moves[0].1, Some('q'))),
+            };
+            alpha = alpha.max(score);
+            scored.push((score, *m));
+        }
+
+        // The best move of this depth is searched first at the next
+        scored.sort_by_key(|(score, _m)| -score);
+        moves = scored.into_iter().map(|(_score, m)| m).collect();
+    }
+    Some((moves[0].0, moves[0].1, Some('q')))
+}
+
+/// ## analyse
+/// Searches the position given by a FEN string to a fixed depth, without mistakes or time limit.
+/// Returns the score in centipawns from the side to move and the best move,
+/// or None if there are no legal moves or the search was cancelled.
+pub fn analyse(fen: &str, depth: usize, cancelled: &AtomicBool) -> Option<(i32, SanMove)> {
+    let game = game_from_fen(fen);
+    let limits = Limits {
+        deadline: None,
+        cancelled,
+    };
+    let mut best = None;
+    let mut alpha = -MATE - 1;
+    for m in ordered_moves(&game) {
+        let score = -search(&play(&game, m), depth.max(1) - 1, -MATE - 1, -alpha, 1, &limits)?;
+        if score > alpha {
+            alpha = score;
+            best = Some((score, (m.0, m.1, Some('q'))));
+        }
+    }
+    best
+}
+
+/// Negamax search with alpha-beta pruning. Scores are seen from the side to move.
+/// Returns None if the limits were exceeded before the search finished.
+fn search(game: &Game, depth: usize, mut alpha: i32, beta: i32, ply: i32, limits: &Limits) -> Option<i32> {
+    if limits.exceeded() {
+        return None;
+    }
+    if matches!(game.game_state, murnion_chess::GameState::Checkmate) {
+        return Some(-(MATE - ply));
+    }
+    if depth == 0 {
+        return Some(evaluate(game));
+    }
+
+    let moves = ordered_moves(game);
+    if moves.is_empty() {
+        return Some(0);
+    }
+    for m in moves {
+        let score = -search(&play(game, m), depth - 1, -beta, -alpha, ply + 1, limits)?;
+        if score >= beta {
+            return Some(beta);
+        }
+        alpha = alpha.max(score);
+    }
+    Some(alpha)
+}
+
+/// The game after a move, pawns reaching the last rank become queens.
+fn play(game: &Game, (from, to): ((usize, usize), (usize, usize))) -> Game {
+    let mut child = game.clone();
+    child.select_promotion('q');
+    child.take_turn(move_to_string(from, to));
+    child
+}
+
+/// The legal moves with captures of valuable pieces by cheap ones first, as they are most likely to be good.
+fn ordered_moves(game: &Game) -> Vec<((usize, usize), (usize, usize))> {
+    let mut moves = legal_moves(game);
+    moves.sort_by_key(|(from, to)| {
+        match game.board[to.0][to.1] {
+            Piece::Empty => 0,
+            victim => piece_value(game.board[from.0][from.1]) - 10 * piece_value(victim),
+        }
+    });
+    moves
+}
+
+/// ## evaluate
+/// Scores a position by material and piece placement, in centipawns from the side to move.
+pub fn evaluate(game: &Game) -> i32 {
+    let mut score = 0;
+    for (rank, pieces) in game.board.iter().enumerate() {
+        for (file, piece) in pieces.iter().enumerate() {
+            let table = match piece {
+                Piece::Pawn(_) => &PAWN_TABLE,
+                Piece::Knight(_) => &KNIGHT_TABLE,
+                Piece::Bishop(_) => &BISHOP_TABLE,
+                Piece::Rook(_) => &ROOK_TABLE,
+                Piece::Queen(_) => &QUEEN_TABLE,
+                Piece::King(_) => &KING_TABLE,
+                Piece::Empty => continue,
+            };
+            match get_colour(*piece) {
+                Some(Colour::White) => score += piece_value(*piece) + table[rank][file],
+                _ => score -= piece_value(*piece) + table[7 - rank][file],
+            }
+        }
+    }
+    match game.current_turn {
+        Colour::White => score,
+        Colour::Black => -score,
+    }
+}
+
+/// Material value of a piece in centipawns. Kings can't be traded, so they are worth nothing.
+fn piece_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn(_) => 100,
+        Piece::Knight(_) => 320,
+        Piece::Bishop(_) => 330,
+        Piece::Rook(_) => 500,
+        Piece::Queen(_) => 900,
+        Piece::King(_) | Piece::Empty => 0,
+    }
+}
+
+/// Xorshift random number generator, good enough for picking mistakes and book moves.
+pub struct Random(u64);
+
+impl Random {
+    pub fn seeded() -> Random {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos() as u64);
+        Random(seed | 1)
+    }
+
+    /// A random number from 0 up to, but not including, n.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n.max(1)
+    }
+
+    /// A random number from -amplitude to amplitude.
+    fn noise(&mut self, amplitude: i32) -> i32 {
+        self.below(2 * amplitude as u64 + 1) as i32 - amplitude
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A level searching exactly the given depth, without mistakes or time limit to speak of.
+    fn exact(depth: usize) -> Level {
+        Level {
+            name: "Exact",
+            depth,
+            time: Duration::from_secs(600),
+            noise: 0,
+            blunders: 0,
+        }
+    }
+
+    /// The FEN string of the position with the colours swapped and the board mirrored vertically.
+    fn mirror(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let ranks: Vec<String> = fields[0]
+            .split('/')
+            .rev()
+            .map(|rank| {
+                rank.chars()
+                    .map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() })
+                    .collect()
+            })
+            .collect();
+        let side = if fields[1] == "w" { "b" } else { "w" };
+        format!("{} {} - - {} {}", ranks.join("/"), side, fields[4], fields[5])
+    }
+
+    #[test]
+    fn mate_in_one() {
+        let fen = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+        let cancelled = AtomicBool::new(false);
+        assert_eq!(analyse(fen, 1, &cancelled), Some((MATE - 1, ((7, 0), (0, 0), Some('q')))));
+        assert_eq!(best_move(fen, exact(3), &cancelled), Some(((7, 0), (0, 0), Some('q'))));
+    }
+
+    #[test]
+    fn symmetric_evaluation() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w - - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+        ]
+        .iter()
+        {
+            let game = game_from_fen(fen);
+            assert_eq!(evaluate(&game), evaluate(&game_from_fen(&mirror(fen))), "{}", fen);
+        }
+        assert_eq!(evaluate(&Game::new()), 0);
+    }
+
+    #[test]
+    fn respects_the_level_depth() {
+        // Taking the pawn on d5 wins it, until the recapture one ply later is seen
+        let fen = "4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1";
+        let cancelled = AtomicBool::new(false);
+        let capture = ((7, 3), (3, 3), Some('q'));
+        assert_eq!(best_move(fen, exact(1), &cancelled), Some(capture));
+        assert_ne!(best_move(fen, exact(2), &cancelled), Some(capture));
+    }
+}
@@ -0,0 +1,178 @@
+use super::*;
+use ggez::graphics::Color;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{imageops, Delay, Frame, Rgba, RgbaImage};
+
+/// Size of a tile in the animation, in pixels. Piece images are drawn at their own size of 45 pixels.
+const TILE_SIZE: u32 = 45;
+/// Height of the caption below the board, in pixels.
+const CAPTION_HEIGHT: u32 = 24;
+/// Each pixel of the caption font is drawn as a square of this many pixels.
+const FONT_SCALE: u32 = 2;
+
+/// 5x7 pixel font for captions, one byte per row with the leftmost pixel in the fifth bit.
+/// Covers the characters of move numbers and Standard Algebraic Notation.
+const FONT: [(char, [u8; 7]); 30] = [
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    ('a', [0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b10001, 0b01111]),
+    ('b', [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b11110]),
+    ('c', [0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('d', [0b00001, 0b00001, 0b01101, 0b10011, 0b10001, 0b10001, 0b01111]),
+    ('e', [0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110]),
+    ('f', [0b00110, 0b01001, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000]),
+    ('g', [0b00000, 0b01111, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110]),
+    ('h', [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001]),
+    ('x', [0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+    ('#', [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010]),
+    ('=', [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+];
+
+/// ## Animation
+/// Settings for exporting the main line of a game as an animated GIF, one frame per position.
+pub struct Animation {
+    pub delay: u32, // Milliseconds each position is shown, the final position is shown three times as long
+    pub captions: bool, // Show the move leading up to each position below the board
+    pub flipped: bool, // Black at the bottom
+}
+
+impl Default for Animation {
+    fn default() -> Animation {
+        Animation {
+            delay: 1000,
+            captions: true,
+            flipped: false,
+        }
+    }
+}
+
+impl Animation {
+    /// Renders the main line of the history with the piece images in RESOURCE_DIR and writes it to a GIF file.
+    pub fn write(&self, history: &MoveTree, path: &path::Path) -> Result<(), String> {
+        let sprites = load_sprites()?;
+
+        // The start position has no move leading up to it
+        let mut positions = vec![(history.start_fen.as_str(), None)];
+        for node in history.main_line() {
+            positions.push((node.fen.as_str(), Some(&node.record)));
+        }
+
+        let mut frames = Vec::new();
+        for (i, (fen, record)) in positions.iter().enumerate() {
+            let image = self.render(&game_from_fen(fen).board, *record, &sprites);
+            let delay = if i + 1 == positions.len() { self.delay * 3 } else { self.delay };
+            frames.push(Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(delay, 1)));
+        }
+
+        let file = fs::File::create(path).map_err(|e| e.to_string())?;
+        let mut encoder = GifEncoder::new(file);
+        encoder.set_repeat(Repeat::Infinite).map_err(|e| e.to_string())?;
+        encoder.encode_frames(frames).map_err(|e| e.to_string())
+    }
+
+    /// Draws a position the way draw::board does, with the last move highlighted and the optional caption below.
+    fn render(&self, board: &[[Piece; 8]; 8], record: Option<&MoveRecord>, sprites: &[(Piece, RgbaImage)]) -> RgbaImage {
+        let caption_height = if self.captions { CAPTION_HEIGHT } else { 0 };
+        let mut image = RgbaImage::from_pixel(TILE_SIZE * 8, TILE_SIZE * 8 + caption_height, rgba(CONTRAST_COLOR));
+
+        for (rank, pieces) in board.iter().enumerate() {
+            for (file, piece) in pieces.iter().enumerate() {
+                let (row, col) = flip_square((rank, file), self.flipped);
+                let mut tile = rgba(if (rank + file) % 2 == 0 { WHITE } else { BLACK });
+                if record.is_some_and(|record| record.from == (rank, file) || record.to == (rank, file)) {
+                    tile = blend(tile, LAST_MOVE_COLOR);
+                }
+                let (x, y) = (col as u32 * TILE_SIZE, row as u32 * TILE_SIZE);
+                imageops::replace(&mut image, &RgbaImage::from_pixel(TILE_SIZE, TILE_SIZE, tile), x, y);
+
+                if let Some((_piece, sprite)) = sprites.iter().find(|(p, _sprite)| p == piece) {
+                    imageops::overlay(&mut image, sprite, x, y);
+                }
+            }
+        }
+
+        if let Some(record) = record.filter(|_record| self.captions) {
+            let caption = match record.colour() {
+                Colour::White => format!("{}. {}", record.number, record.san),
+                Colour::Black => format!("{}... {}", record.number, record.san),
+            };
+            draw_caption(&mut image, &caption);
+        }
+        image
+    }
+}
+
+/// Loads the piece images from the resource directory.
+fn load_sprites() -> Result<Vec<(Piece, RgbaImage)>, String> {
+    SPRITES
+        .iter()
+        .map(|(piece, file)| {
+            let path = path::Path::new(RESOURCE_DIR).join(file.trim_start_matches('/'));
+            image::open(&path)
+                .map(|sprite| (*piece, sprite.to_rgba8()))
+                .map_err(|e| format!("{}: {}", path.display(), e))
+        })
+        .collect()
+}
+
+/// Draws a caption centered in the strip below the board.
+fn draw_caption(image: &mut RgbaImage, caption: &str) {
+    let advance = 6 * FONT_SCALE;
+    let width = caption.chars().count() as u32 * advance;
+    let left = (image.width().saturating_sub(width)) / 2;
+    let top = TILE_SIZE * 8 + (CAPTION_HEIGHT - 7 * FONT_SCALE) / 2;
+
+    for (i, c) in caption.chars().enumerate() {
+        // Characters missing from the font, like spaces, are left blank
+        let rows = match FONT.iter().find(|(glyph, _rows)| *glyph == c) {
+            Some((_glyph, rows)) => rows,
+            None => continue,
+        };
+        for (y, row) in rows.iter().enumerate() {
+            for x in 0..5 {
+                if row & (0b10000 >> x) != 0 {
+                    let pixel = RgbaImage::from_pixel(FONT_SCALE, FONT_SCALE, rgba(WHITE));
+                    imageops::replace(
+                        image,
+                        &pixel,
+                        left + i as u32 * advance + x * FONT_SCALE,
+                        top + y as u32 * FONT_SCALE,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Converts an opaque GUI colour to a pixel.
+fn rgba(color: Color) -> Rgba<u8> {
+    blend(Rgba([0, 0, 0, 255]), Color { a: 1.0, ..color })
+}
+
+/// Draws a translucent GUI colour over a pixel.
+fn blend(pixel: Rgba<u8>, color: Color) -> Rgba<u8> {
+    let mix = |base: u8, value: f32| (base as f32 * (1.0 - color.a) + value * 255.0 * color.a).round() as u8;
+    Rgba([
+        mix(pixel[0], color.r),
+        mix(pixel[1], color.g),
+        mix(pixel[2], color.b),
+        255,
+    ])
+}
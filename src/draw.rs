@@ -93,9 +93,15 @@ fn draw_sizeable_text(ctx: &mut Context, x: f32, y: f32, w_pixel: f32, h_pixel:
 
 /// ## board
 /// Draws the board and the pieces on it. Also draws highlights in case of highlighted moves or selected squares.
+/// A flipped board is drawn with black at the bottom, the coordinates along the edges follow it.
+/// The coordinates and the highlight of the last move can be switched off.
+/// A previewed engine line replaces the displayed position, without its highlights.
 pub fn board(appstate: &AppState, ctx: &mut Context) {
+    let pieces = appstate.preview.as_ref().map_or(&appstate.game.board, |game| &game.board);
+    let displayed_move = appstate.displayed_move().filter(|_record| appstate.preview.is_none());
     for _row in 0..8 {
         for _col in 0..8 {
+            let square = flip_square((_row as usize, _col as usize), appstate.flipped);
 
             // Decide tile color
             let color = match _col % 2 {
@@ -118,21 +124,43 @@ pub fn board(appstate: &AppState, ctx: &mut Context) {
             // Draw tile
             draw_square(ctx, _col as f32, _row as f32, color);
 
+            // Draw the last move and a checked king
+            if let Some(record) = displayed_move {
+                if appstate.last_move_shown && (record.from == square || record.to == square) {
+                    draw_square(ctx, _col as f32, _row as f32, LAST_MOVE_COLOR);
+                }
+                if record.check
+                    && appstate.game.board[square.0][square.1] == Piece::King(appstate.game.current_turn)
+                {
+                    draw_square(ctx, _col as f32, _row as f32, CHECK_COLOR);
+                }
+            }
+
             // Draw highlighted_squares
             if appstate.highlighted_squares
-            .contains(&(_row as usize, _col as usize)) { 
+            .contains(&square) { 
                 draw_square(ctx, _col as f32, _row as f32, HIGHLIGHTED_COLOR); 
             }
 
             // Draw selected square
             if let Some(t) = appstate.selected_square {
-                if _col == t.1 as i32 && _row == t.0 as i32 {
+                if square == t {
                     draw_square(ctx, _col as f32, _row as f32, SELECTED_COLOR);
                 }
             }
 
+            // Draw coordinates along the left and bottom edges, in the colour of the other tiles
+            let contrast = if (_row + _col) % 2 == 0 { BLACK } else { WHITE };
+            let name = square_to_string(square);
+            if appstate.coordinates && _col == 0 {
+                draw_sizeable_text(ctx, _col as f32 + 0.1, _row as f32 + 0.15, 16.0, 16.0, contrast, name[1..].to_string());
+            }
+            if appstate.coordinates && _row == 7 {
+                draw_sizeable_text(ctx, _col as f32 + 0.9, _row as f32 + 0.85, 16.0, 16.0, contrast, name[..1].to_string());
+            }
+
             // Draw piece
-            match appstate.game.board[_row as usize][_col as usize] {
+            match pieces[square.0][square.1] {
                 Piece::Empty => (),
                 _piece => draw_sprite(appstate, ctx, _col as f32, _row as f32, _piece),
             }
@@ -140,6 +168,59 @@ pub fn board(appstate: &AppState, ctx: &mut Context) {
     }
 }
 
+/// ## hint
+/// Draws the hint for the displayed position as arrows on top of the board,
+/// the best move in green and the threat in red.
+pub fn hint(appstate: &AppState, ctx: &mut Context) {
+    if let Some(hint) = appstate.hint.as_ref().filter(|hint| hint.path == appstate.cursor) {
+        if let Some((from, to, _promotion)) = hint.threat {
+            draw_arrow(ctx, from, to, appstate.flipped, THREAT_COLOR);
+        }
+        if let Some((from, to, _promotion)) = hint.best {
+            draw_arrow(ctx, from, to, appstate.flipped, HINT_COLOR);
+        }
+    }
+}
+
+/// ## draw_arrow
+/// Helper function that draws an arrow from the center of one square to another.
+/// The squares are (rank, file) tuples, drawn where they are on the possibly flipped board.
+fn draw_arrow(ctx: &mut Context, from: (usize, usize), to: (usize, usize), flipped: bool, color: Color) {
+    let center = |square: (usize, usize)| {
+        let (row, col) = flip_square(square, flipped);
+        (
+            (col as f32 + 0.5) * GRID_CELL_SIZE.0 as f32,
+            (row as f32 + 0.5) * GRID_CELL_SIZE.1 as f32,
+        )
+    };
+    let (x1, y1) = center(from);
+    let (x2, y2) = center(to);
+    let length = ((x2 - x1).powi(2) + (y2 - y1).powi(2)).sqrt();
+    if length == 0.0 {
+        return;
+    }
+
+    // Unit vectors along and across the arrow, the head is a triangle ending at the target's center
+    let (dx, dy) = ((x2 - x1) / length, (y2 - y1) / length);
+    let head = GRID_CELL_SIZE.0 as f32 * 0.35;
+    let (bx, by) = (x2 - dx * head, y2 - dy * head);
+    let shaft = graphics::Mesh::new_line(ctx, &[[x1, y1], [bx, by]], GRID_CELL_SIZE.0 as f32 * 0.15, color)
+        .expect("Failed to create arrow.");
+    let tip = graphics::Mesh::new_polygon(
+        ctx,
+        graphics::DrawMode::fill(),
+        &[
+            [x2, y2],
+            [bx - dy * head / 2.0, by + dx * head / 2.0],
+            [bx + dy * head / 2.0, by - dx * head / 2.0],
+        ],
+        color,
+    )
+    .expect("Failed to create arrow.");
+    graphics::draw(ctx, &shaft, graphics::DrawParam::default()).expect("Failed to draw arrow.");
+    graphics::draw(ctx, &tip, graphics::DrawParam::default()).expect("Failed to draw arrow.");
+}
+
 /// ## promotion_selector
 /// Draws the promotion selector on the right side of the screen. 
 /// Highlights whichever piece is chosen for selection
@@ -169,21 +250,41 @@ pub fn promotion_selector(appstate: &AppState, ctx: &mut Context) {
 /// ## history
 /// Draws the history viewer on the right side of the screen.
 pub fn history(appstate: &AppState, ctx: &mut Context) {
-    // Draw history label text
-    draw_text(ctx, 9.5, 3.25, WHITE, format!("History"));
+    // Draw history label text, or the progress of the review and the accuracy of both players
+    match &appstate.review {
+        Some(review) if review.is_finished() => {
+            let accuracy = format!(
+                "White {:.0}%  Black {:.0}%",
+                review.accuracy(Colour::White).unwrap_or(100.0),
+                review.accuracy(Colour::Black).unwrap_or(100.0),
+            );
+            draw_sizeable_text(ctx, 9.5, 3.25, 20.0, 20.0, WHITE, accuracy);
+        }
+        Some(review) => {
+            let (scored, total) = review.progress();
+            draw_sizeable_text(ctx, 9.5, 3.25, 20.0, 20.0, WHITE, format!("Reviewing {}/{}", scored, total));
+        }
+        None => draw_text(ctx, 9.5, 3.25, WHITE, format!("History")),
+    }
+
+    let rows = appstate.history.rows();
 
-    // Draw squares and count
-    for i in 0..12 {
-        // Draws squares
+    // Draw squares and count for the visible rows
+    for i in 0..appstate.visible_history_rows() {
+        let row = i + appstate.history_scroll;
+        let depth = rows.get(row).map_or(0, |r| r.depth);
+
+        // Draws squares, variations are drawn in a darker color than the main line
         draw_rectangle(
             ctx, 
             8.5 + 1.0 / 3.0, 
             3.5 + (i as f32) / 3.0, 
             2.0 / 3.0,
             1.0 / 3.0,
-            match i % 2 {
-                0 => WHITE,
-                _ => BLACK,
+            match (depth, row % 2) {
+                (0, 0) => WHITE,
+                (0, _) => BLACK,
+                _ => VARIATION_COLOR,
             }
         );
         draw_rectangle(
@@ -192,33 +293,242 @@ pub fn history(appstate: &AppState, ctx: &mut Context) {
             3.5 + (i as f32) / 3.0, 
             2.0 / 3.0,
             1.0 / 3.0,
-            match i % 2 {
-                0 => BLACK,
-                _ => WHITE,
+            match (depth, row % 2) {
+                (0, 0) => BLACK,
+                (0, _) => WHITE,
+                _ => VARIATION_COLOR,
             }
         );
 
-        // Draws turn numbers on left of history viewer
-        draw_text(
-            ctx, 
-            8.5, 
-            3.5 + (0.5 + i  as f32) / 3.0, 
-            WHITE, 
-            format!("{}", i + 1)
-        );
+        // Draws move numbers on left of history viewer, smaller for variations
+        if let Some(r) = rows.get(row) {
+            let size = if r.depth == 0 { 30.0 } else { 20.0 };
+            draw_sizeable_text(
+                ctx, 
+                8.5, 
+                3.5 + (0.5 + i  as f32) / 3.0, 
+                size,
+                size,
+                WHITE, 
+                format!("{}", r.number)
+            );
+        }
+
+        // Draw out the moves in history viewer, shrinking long moves to fit their square
+        for col in 0..2 {
+            let path = rows.get(row).and_then(|r| r.cells[col].as_ref());
+            if let Some(record) = path.and_then(|path| appstate.history.record(path)) {
+                // Reviewed moves of the main line are annotated with their class
+                let symbol = path
+                    .and_then(|path| appstate.review.as_ref()?.class(&appstate.history, path))
+                    .map_or("", |class| class.symbol());
+                let san = format!("{}{}", record.san, symbol);
+                let size = (120.0 / san.len().max(5) as f32).min(24.0);
+                draw_sizeable_text(
+                    ctx,
+                    9.0 + (1.0 + 4.0 * col as f32) / 6.0,
+                    3.5 + (0.5 + i as f32) / 3.0,
+                    size,
+                    size,
+                    if path == Some(&appstate.cursor) {
+                        SELECTED_COLOR
+                    } else if depth > 0 {
+                        WHITE
+                    } else {
+                        CONTRAST_COLOR
+                    },
+                    san
+                );
+            }
+        }
     }
 
-    // Draw out history markers in history viewer
-    for i in 0..24 {
-        if i < appstate.history.len() {
-            match i % 2 {
-                0 => draw_text(ctx, 9.0 + 1.0 / 6.0, 3.5 + (0.5 + i as f32 / 2.0) / 3.0, CONTRAST_COLOR, format!("{}", i + 1)),
-                _ => draw_text(ctx, 9.0 + 5.0 / 6.0, 3.5 + (0.5 + (i as f32 / 2.0).floor()) / 3.0, CONTRAST_COLOR, format!("{}", i + 1)),
+    scrollbar(appstate, ctx);
+}
+
+/// ## scrollbar
+/// Draws the scrollbar of the history viewer.
+/// The thumb covers the part of the history currently visible in the viewer.
+fn scrollbar(appstate: &AppState, ctx: &mut Context) {
+    let visible = appstate.visible_history_rows();
+    let track_height = visible as f32 / 3.0;
+    draw_rectangle(ctx, SCROLLBAR_X, 3.5, SCROLLBAR_WIDTH, track_height, BLACK);
+
+    let rows = appstate.history_rows().max(visible) as f32;
+    draw_rectangle(
+        ctx,
+        SCROLLBAR_X,
+        3.5 + track_height * appstate.history_scroll as f32 / rows,
+        SCROLLBAR_WIDTH,
+        track_height * visible as f32 / rows,
+        WHITE
+    );
+}
+
+/// ## eval_bar
+/// Draws the evaluation bar between the board and the side panel while the analysis is on.
+/// White's share grows from white's side of the board, so it follows the board when flipped.
+pub fn eval_bar(appstate: &AppState, ctx: &mut Context) {
+    if appstate.analysis.is_none() {
+        return;
+    }
+    draw_rectangle(ctx, EVAL_BAR_X, 0.0, EVAL_BAR_WIDTH, 8.0, Color::BLACK);
+    if let Some(score) = appstate.evaluation() {
+        let white = 8.0 * score.expectation();
+        let y = if appstate.flipped { 0.0 } else { 8.0 - white };
+        draw_rectangle(ctx, EVAL_BAR_X, y, EVAL_BAR_WIDTH, white, Color::WHITE);
+    }
+}
+
+/// ## analysis
+/// Draws the engine lines found by the analysis below the history viewer, best first.
+/// Each line shows its evaluation, search depth and as many of its moves as fit.
+pub fn analysis(appstate: &AppState, ctx: &mut Context) {
+    if appstate.analysis.is_none() {
+        return;
+    }
+    let height = ANALYSIS_HEIGHT / appstate.multipv as f32;
+    for i in 0..appstate.multipv {
+        let top = ANALYSIS_Y + i as f32 * height;
+        draw_rectangle(ctx, 8.5, top, 2.0, height, if i % 2 == 0 { WHITE } else { BLACK });
+
+        match appstate.analysis_lines.get(i).and_then(Option::as_ref) {
+            Some((info, line)) => {
+                let score = info.score.for_white(appstate.game.current_turn);
+                let rows = wrap(line, 22);
+                let shown = ((height - 0.2) / 0.2).floor().max(1.0) as usize;
+                draw_sizeable_text(ctx, 9.5, top + 0.1, 16.0, 16.0, CONTRAST_COLOR, format!("{}  depth {}", score, info.depth));
+                for (j, row) in rows.iter().take(shown).enumerate() {
+                    draw_sizeable_text(ctx, 9.5, top + 0.3 + j as f32 * 0.2, 14.0, 14.0, CONTRAST_COLOR, row.clone());
+                }
+            }
+            None if i == 0 => {
+                draw_sizeable_text(ctx, 9.5, top + height / 2.0, 16.0, 16.0, CONTRAST_COLOR, "Analysing...".to_string());
+            }
+            None => (),
+        }
+    }
+}
+
+/// ## book
+/// Draws the book moves of the displayed position with their share of the weights, below the history viewer.
+/// The engine lines and the endgame tables take their place when shown.
+pub fn book(appstate: &AppState, ctx: &mut Context) {
+    let moves = appstate.book_moves();
+    if appstate.analysis.is_some() || appstate.probe.is_some() || moves.is_empty() {
+        return;
+    }
+    let total = moves.iter().map(|(_m, weight)| *weight as u32).sum::<u32>().max(1);
+    let fen = appstate.game.get_fen();
+    draw_rectangle(ctx, 8.5, ANALYSIS_Y, 2.0, ANALYSIS_HEIGHT, WHITE);
+    draw_sizeable_text(ctx, 9.5, ANALYSIS_Y + 0.1, 16.0, 16.0, CONTRAST_COLOR, "Book".to_string());
+    let shown = ((ANALYSIS_HEIGHT - 0.2) / 0.2).floor().max(1.0) as usize;
+    for (i, (m, weight)) in moves.iter().take(shown).enumerate() {
+        let share = 100.0 * *weight as f32 / total as f32;
+        let row = format!("{}  {:.0}%  ({})", san::variation(&fen, &[*m]).join(" "), share, weight);
+        draw_sizeable_text(ctx, 9.5, ANALYSIS_Y + 0.3 + i as f32 * 0.2, 14.0, 14.0, CONTRAST_COLOR, row);
+    }
+}
+
+/// ## tablebase
+/// Draws the result of the displayed position according to the endgame tables below the history viewer,
+/// with the result and DTZ of every move. The DTZ-optimal moves are drawn in the selection colour.
+/// The engine lines take their place while analysis is on.
+pub fn tablebase(appstate: &AppState, ctx: &mut Context) {
+    let probe = match &appstate.probe {
+        Some(probe) if appstate.analysis.is_none() => probe,
+        _ => return,
+    };
+    draw_rectangle(ctx, 8.5, ANALYSIS_Y, 2.0, ANALYSIS_HEIGHT, WHITE);
+    let result = format!("Tablebase: {}  DTZ {}", tablebase::describe(probe.wdl), probe.dtz.abs());
+    draw_sizeable_text(ctx, 9.5, ANALYSIS_Y + 0.1, 16.0, 16.0, CONTRAST_COLOR, result);
+    let best = probe.best_moves();
+    let shown = ((ANALYSIS_HEIGHT - 0.2) / 0.2).floor().max(1.0) as usize;
+    for (i, (m, wdl, plies)) in probe.moves.iter().take(shown).enumerate() {
+        let row = format!("{}  {}  DTZ {}", san::variation(&probe.fen, &[*m]).join(" "), tablebase::describe(*wdl), plies);
+        let colour = if best.contains(m) { SELECTED_COLOR } else { CONTRAST_COLOR };
+        draw_sizeable_text(ctx, 9.5, ANALYSIS_Y + 0.3 + i as f32 * 0.2, 14.0, 14.0, colour, row);
+    }
+}
+
+/// Joins words into lines of at most the given number of characters, a longer word gets a line of its own.
+fn wrap(words: &[String], width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for word in words {
+        match lines.last_mut() {
+            Some(line) if line.len() + 1 + word.len() <= width => {
+                line.push(' ');
+                line.push_str(word);
             }
-        } else {
-            break;
+            _ => lines.push(word.clone()),
         }
     }
+    lines
+}
+
+/// ## toolbar
+/// Draws the toolbar below the history viewer.
+/// Buttons that currently don't do anything are drawn darker.
+pub fn toolbar(appstate: &AppState, ctx: &mut Context) {
+    let width = 2.0 / TOOLBAR.len() as f32;
+    for (i, (button, label)) in TOOLBAR.iter().enumerate() {
+        let x = 8.5 + i as f32 * width;
+        draw_rectangle(
+            ctx,
+            x + 1.0 / 30.0,
+            TOOLBAR_Y,
+            width - 1.0 / 15.0,
+            1.0 / 3.0,
+            if appstate.is_enabled(*button) { WHITE } else { BLACK }
+        );
+        draw_sizeable_text(
+            ctx,
+            x + width / 2.0,
+            TOOLBAR_Y + 1.0 / 6.0,
+            16.0,
+            16.0,
+            CONTRAST_COLOR,
+            label.to_string()
+        );
+    }
+}
+
+/// ## message
+/// Draws the current message, if any, in a banner at the bottom of the board.
+pub fn message(appstate: &AppState, ctx: &mut Context) {
+    if let Some(message) = &appstate.message {
+        draw_rectangle(ctx, 0.0, 7.5, 8.0, 0.5, CONTRAST_COLOR);
+        draw_sizeable_text(ctx, 4.0, 7.75, 24.0, 24.0, WHITE, message.clone());
+    }
+}
+
+/// ## fen_input
+/// Draws the FEN entry box across the middle of the screen while it is open.
+pub fn fen_input(appstate: &AppState, ctx: &mut Context) {
+    if let Some(input) = &appstate.fen_input {
+        draw_rectangle(ctx, 0.0, 3.25, 11.0, 1.5, CONTRAST_COLOR);
+        draw_sizeable_text(
+            ctx,
+            5.5,
+            3.6,
+            24.0,
+            24.0,
+            WHITE,
+            "Enter a FEN string, Enter to load, Esc to cancel".to_string()
+        );
+        draw_rectangle(ctx, 0.25, 4.0, 10.5, 0.5, WHITE);
+        draw_sizeable_text(ctx, 5.5, 4.25, 16.0, 16.0, CONTRAST_COLOR, format!("{}_", input));
+    }
+}
+
+/// ## resume_prompt
+/// Draws the question whether to resume the last session while it is asked.
+pub fn resume_prompt(appstate: &AppState, ctx: &mut Context) {
+    if appstate.resume.is_some() {
+        draw_rectangle(ctx, 0.0, 3.25, 11.0, 1.5, CONTRAST_COLOR);
+        draw_sizeable_text(ctx, 5.5, 3.75, 45.0, 45.0, WHITE, "Resume last game?".to_string());
+        draw_sizeable_text(ctx, 5.5, 4.35, 24.0, 24.0, WHITE, "Y to resume, N to start a new game".to_string());
+    }
 }
 
 /// ## info_text
@@ -226,6 +536,29 @@ pub fn history(appstate: &AppState, ctx: &mut Context) {
 pub fn info_text(appstate: &AppState, ctx: &mut Context) {
     draw_text(ctx, 9.5, 0.5, WHITE, format!("Turn: {}", appstate.game.turn));
     draw_text(ctx, 9.5, 0.25, WHITE, format!("{:?} to move", appstate.game.current_turn));
+    // The status line goes below the name of the opening, however many lines it wraps over
+    let mut status_y = 0.9;
+    if let Some(opening) = eco::opening(&appstate.history, &appstate.cursor) {
+        let words: Vec<String> = format!("{} {}", opening.eco, opening.name).split_whitespace().map(String::from).collect();
+        let mut y = 0.72;
+        for row in wrap(&words, 22) {
+            let text = graphics::Text::new(
+                graphics::TextFragment::from(row.clone()).scale(graphics::PxScale { x: 14.0, y: 14.0 }),
+            );
+            let height = text.dimensions(ctx).h / GRID_CELL_SIZE.1 as f32;
+            draw_sizeable_text(ctx, 9.5, y, 14.0, 14.0, WHITE, row);
+            status_y = y + 0.18;
+            y += height;
+        }
+    }
+    if let Some(versus) = &appstate.versus {
+        let (white, black) = versus.names();
+        let round = format!("Game {}/{}: {} - {}", versus.round(), versus.games(), white, black);
+        draw_sizeable_text(ctx, 9.5, status_y, 16.0, 16.0, WHITE, round);
+    } else if let Some(name) = appstate.computer_name() {
+        let status = if appstate.is_thinking() { "thinking" } else { "waiting" };
+        draw_sizeable_text(ctx, 9.5, status_y, 16.0, 16.0, WHITE, format!("{} ({:?}) {}", name, appstate.computer_colour, status));
+    }
 
     match appstate.game.game_state {
         murnion_chess::GameState::Checkmate if !appstate.viewing_history => {
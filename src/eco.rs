@@ -0,0 +1,98 @@
+use super::*;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// The bundled table of openings: ECO code, name and moves from the start position, tab separated.
+/// It holds a selection of common openings, not every line of the ECO classification.
+const TABLE: &str = include_str!("../resources/eco.tsv");
+
+/// ## Opening
+/// A named opening of the ECO classification.
+pub struct Opening {
+    pub eco: String,
+    pub name: String,
+}
+
+/// The openings of the table by the position they end in, replayed once when first needed.
+fn openings() -> &'static HashMap<String, Opening> {
+    static OPENINGS: OnceLock<HashMap<String, Opening>> = OnceLock::new();
+    OPENINGS.get_or_init(|| read(TABLE))
+}
+
+/// Reads a table of openings, replaying the moves of each to find its position.
+/// Lines whose moves can't be played are left out. The first opening of a position is kept.
+fn read(table: &str) -> HashMap<String, Opening> {
+    let mut openings = HashMap::new();
+    for line in table.lines().skip(1) {
+        let mut columns = line.split('\t');
+        let (eco, name, moves) = match (columns.next(), columns.next(), columns.next()) {
+            (Some(eco), Some(name), Some(moves)) => (eco, name, moves),
+            _ => continue,
+        };
+        if let Some(fen) = replay(moves) {
+            openings.entry(position(&fen)).or_insert(Opening {
+                eco: eco.to_string(),
+                name: name.to_string(),
+            });
+        }
+    }
+    openings
+}
+
+/// The FEN string of the position reached by playing moves in SAN, with move numbers, from the start position.
+fn replay(moves: &str) -> Option<String> {
+    let mut game = Game::new();
+    for san in moves.split_whitespace().filter(|token| !token.ends_with('.')) {
+        let (from, to, promotion) = san::san_to_move(&game, san).ok()?;
+        game.select_promotion(promotion.unwrap_or('q'));
+        game.take_turn(move_to_string(from, to));
+    }
+    Some(game.get_fen())
+}
+
+/// The placement, side to move and castling rights of a FEN string.
+/// The en passant square and move counters are left out so that transpositions compare equal.
+fn position(fen: &str) -> String {
+    fen.split_whitespace().take(3).collect::<Vec<&str>>().join(" ")
+}
+
+/// ## opening
+/// The opening of the line leading up to the position at the end of the path:
+/// the last position along it that is in the table, so that transpositions into an opening are recognised.
+pub fn opening(history: &MoveTree, path: &[usize]) -> Option<&'static Opening> {
+    history
+        .line(path)
+        .iter()
+        .rev()
+        .find_map(|node| openings().get(&position(&node.fen)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_line_replays() {
+        for line in TABLE.lines().skip(1) {
+            let moves = line.split('\t').nth(2).unwrap_or_default();
+            assert!(replay(moves).is_some(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn transpositions() {
+        // The Nimzo-Indian reached from the English Opening
+        let history = pgn::read("1. c4 e6 2. Nc3 Nf6 3. d4 Bb4 4. e3 *").unwrap();
+        let opening = opening(&history, &history.line_end(&[])).unwrap();
+        assert_eq!(opening.eco, "E40");
+        assert_eq!(opening.name, "Nimzo-Indian Defense: Rubinstein Variation");
+    }
+
+    #[test]
+    fn last_known_position() {
+        // Moves leaving the table keep the opening they left
+        let history = pgn::read("1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 h6 *").unwrap();
+        assert_eq!(opening(&history, &history.line_end(&[])).unwrap().eco, "C70");
+        assert!(opening(&history, &[]).is_none());
+    }
+}
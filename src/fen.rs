@@ -0,0 +1,130 @@
+use super::*;
+
+/// ## validate
+/// Checks that a FEN string describes a position the game can be started from,
+/// so that it can safely be passed to Game::set_state_from_fen.
+/// Every FEN string from outside the program, given on the command line, pasted or read from PGN, goes through here.
+pub fn validate(fen: &str) -> Result<(), String> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    if fields.len() != 6 {
+        return Err(format!("expected 6 fields, found {}", fields.len()));
+    }
+
+    let ranks: Vec<&str> = fields[0].split('/').collect();
+    if ranks.len() != 8 {
+        return Err(format!("expected 8 ranks, found {}", ranks.len()));
+    }
+    let (mut white_kings, mut black_kings) = (0, 0);
+    for (i, rank) in ranks.iter().enumerate() {
+        let mut files = 0;
+        for c in rank.chars() {
+            match c {
+                '1'..='8' => files += c.to_digit(10).unwrap_or(0) as usize,
+                'K' | 'k' | 'Q' | 'q' | 'R' | 'r' | 'B' | 'b' | 'N' | 'n' | 'P' | 'p' => {
+                    if (c == 'P' || c == 'p') && (i == 0 || i == 7) {
+                        return Err("pawns can't stand on the first or last rank".to_string());
+                    }
+                    match c {
+                        'K' => white_kings += 1,
+                        'k' => black_kings += 1,
+                        _ => (),
+                    }
+                    files += 1;
+                }
+                _ => return Err(format!("unknown piece '{}'", c)),
+            }
+        }
+        if files != 8 {
+            return Err(format!("rank {} has {} files", 8 - i, files));
+        }
+    }
+    if white_kings != 1 || black_kings != 1 {
+        return Err("each side must have exactly one king".to_string());
+    }
+
+    if fields[1] != "w" && fields[1] != "b" {
+        return Err(format!("unknown side to move '{}'", fields[1]));
+    }
+    if fields[2] != "-"
+        && (fields[2].is_empty() || !fields[2].chars().all(|c| "KQkq".contains(c)))
+    {
+        return Err(format!("invalid castling rights '{}'", fields[2]));
+    }
+    if fields[3] != "-" {
+        match string_to_square(fields[3]) {
+            Some((2, _)) | Some((5, _)) => (),
+            _ => return Err(format!("invalid en passant square '{}'", fields[3])),
+        }
+    }
+    if fields[4].parse::<usize>().is_err() {
+        return Err(format!("invalid halfmove clock '{}'", fields[4]));
+    }
+    match fields[5].parse::<usize>() {
+        Ok(number) if number > 0 => (),
+        _ => return Err(format!("invalid fullmove number '{}'", fields[5])),
+    }
+
+    // The side to move could capture a king in check
+    let game = game_from_fen(fen);
+    if san::in_check(&game.board, opponent(game.current_turn)) {
+        return Err("the side not to move is in check".to_string());
+    }
+    Ok(())
+}
+
+/// ## null_move
+/// The FEN string of the position with the other side to move, as if the side to move had passed.
+/// Returns None if the side to move is in check, as passing would leave its king to be captured.
+pub fn null_move(fen: &str) -> Option<String> {
+    let game = game_from_fen(fen);
+    if san::in_check(&game.board, game.current_turn) {
+        return None;
+    }
+    let mut fields: Vec<String> = fen.split_whitespace().map(|field| field.to_string()).collect();
+    if fields.len() != 6 {
+        return None;
+    }
+    fields[1] = if fields[1] == "w" { "b" } else { "w" }.to_string();
+    fields[3] = "-".to_string();
+    Some(fields.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The reason a FEN string is rejected, or "valid".
+    fn check(fen: &str) -> String {
+        validate(fen).err().unwrap_or_else(|| "valid".to_string())
+    }
+
+    #[test]
+    fn accepts_positions() {
+        assert_eq!(check("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), "valid");
+        assert_eq!(check("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"), "valid");
+        assert_eq!(check("4k3/8/8/8/8/8/8/4K3 b - - 49 80"), "valid");
+    }
+
+    #[test]
+    fn rejects_malformed_fields() {
+        assert_eq!(check("8/8/8/8/8/8/8/8 w - - 0"), "expected 6 fields, found 5");
+        assert_eq!(check("4k3/8/8/8/8/8/4K3 w - - 0 1"), "expected 8 ranks, found 7");
+        assert_eq!(check("4k3/8/8/8/8/8/8/4K4 w - - 0 1"), "rank 1 has 9 files");
+        assert_eq!(check("4k3/8/8/8/8/8/8/4K2 w - - 0 1"), "rank 1 has 7 files");
+        assert_eq!(check("4k3/8/8/8/8/8/8/4X3 w - - 0 1"), "unknown piece 'X'");
+        assert_eq!(check("4k3/8/8/8/8/8/8/4K3 x - - 0 1"), "unknown side to move 'x'");
+        assert_eq!(check("4k3/8/8/8/8/8/8/4K3 w KX - 0 1"), "invalid castling rights 'KX'");
+        assert_eq!(check("4k3/8/8/8/8/8/8/4K3 w - e4 0 1"), "invalid en passant square 'e4'");
+        assert_eq!(check("4k3/8/8/8/8/8/8/4K3 w - - x 1"), "invalid halfmove clock 'x'");
+        assert_eq!(check("4k3/8/8/8/8/8/8/4K3 w - - 0 0"), "invalid fullmove number '0'");
+    }
+
+    #[test]
+    fn rejects_impossible_positions() {
+        assert_eq!(check("P3k3/8/8/8/8/8/8/4K3 w - - 0 1"), "pawns can't stand on the first or last rank");
+        assert_eq!(check("4k3/8/8/8/8/8/8/4K2p w - - 0 1"), "pawns can't stand on the first or last rank");
+        assert_eq!(check("8/8/8/8/8/8/8/4K3 w - - 0 1"), "each side must have exactly one king");
+        assert_eq!(check("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"), "each side must have exactly one king");
+        assert_eq!(check("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"), "the side not to move is in check");
+    }
+}
@@ -0,0 +1,93 @@
+use super::*;
+use san::SanMove;
+
+/// Time the engine gets to find each move of a hint.
+const HINT_TIME: Duration = Duration::from_secs(1);
+
+/// A search for one move of a hint, by the UCI engine kept for hints or the built-in computer.
+enum Searcher {
+    Engine,
+    Computer(ai::Search),
+}
+
+impl Searcher {
+    /// Starts searching the position given by a FEN string.
+    /// The UCI engine is used if there is one, otherwise the built-in computer at the given level.
+    fn start(fen: &str, engine: Option<&mut uci::Engine>, level: ai::Level) -> Searcher {
+        match engine {
+            Some(engine) => {
+                engine.go(fen, &[], HINT_TIME);
+                Searcher::Engine
+            }
+            None => Searcher::Computer(ai::Search::start(fen.to_string(), level)),
+        }
+    }
+
+    /// The move found once the search is finished, or why none was found.
+    fn poll(&mut self, engine: Option<&mut uci::Engine>) -> Option<Result<SanMove, String>> {
+        match (self, engine) {
+            (Searcher::Computer(search), _engine) => search.poll().map(Ok),
+            (Searcher::Engine, None) => Some(Err("The engine was stopped".to_string())),
+            (Searcher::Engine, Some(engine)) => engine.poll().into_iter().find_map(|event| match event {
+                uci::Event::BestMove(bestmove) => {
+                    Some(uci::uci_to_move(&bestmove).ok_or(format!("The engine found no move ({})", bestmove)))
+                }
+                uci::Event::Crashed => Some(Err("The engine crashed".to_string())),
+                uci::Event::TimedOut => Some(Err("The engine stopped answering".to_string())),
+                uci::Event::Info(_info) => None,
+            }),
+        }
+    }
+}
+
+/// ## Hint
+/// The best move in a position, and the threat: the move the opponent would play if it were their turn.
+/// Both are searched in the background, one after the other, and filled in once found.
+/// UCI engines aren't owned by the hint but passed in, so that one engine serves every hint.
+/// The engine playing against the user can't be used, as its own search may be running.
+pub struct Hint {
+    pub path: Path, // Position in the history the hint is for
+    pub best: Option<SanMove>,
+    pub threat: Option<SanMove>,
+    threat_fen: Option<String>, // Position searched for the threat once the best move is found
+    level: ai::Level, // Level of the built-in computer, used without an engine
+    search: Option<Searcher>,
+}
+
+impl Hint {
+    /// Starts looking for a hint for the position at the end of the path.
+    /// There is no threat while the side to move is in check, as it can't pass.
+    pub fn start(history: &MoveTree, path: Path, engine: Option<&mut uci::Engine>, level: ai::Level) -> Hint {
+        let fen = history.fen(&path);
+        Hint {
+            path,
+            best: None,
+            threat: None,
+            threat_fen: fen::null_move(fen),
+            level,
+            search: Some(Searcher::start(fen, engine, level)),
+        }
+    }
+
+    /// Checks whether the running search is done, fills in what it found and starts the next one.
+    /// Takes the same engine as start.
+    pub fn update(&mut self, engine: Option<&mut uci::Engine>) -> Result<(), String> {
+        let mut engine = engine;
+        if let Some(result) = self.search.as_mut().and_then(|searcher| searcher.poll(engine.as_deref_mut())) {
+            let found = result?;
+            if self.best.is_none() {
+                self.best = Some(found);
+                self.search = self.threat_fen.take().map(|fen| Searcher::start(&fen, engine, self.level));
+            } else {
+                self.threat = Some(found);
+                self.search = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks whether a move of the hint is still being searched for.
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+}
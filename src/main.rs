@@ -1,14 +1,74 @@
-use ggez::{conf, event, graphics, Context, ContextBuilder, GameError, GameResult};
+use clipboard::{ClipboardContext, ClipboardProvider};
+use ggez::{conf, event, filesystem, graphics, Context, ContextBuilder, GameError, GameResult};
 use murnion_chess::{Colour, Game, Piece};
-use std::path;
+use record::MoveRecord;
+use session::Session;
+use std::time::Duration;
+use std::{env, fs, path, process};
+use tree::{MoveTree, Path};
 
+mod ai;
+mod animation;
+mod book;
 mod draw;
+mod eco;
+mod fen;
+mod hint;
+mod perft;
+mod pgn;
+mod record;
+mod review;
+mod san;
+mod session;
+mod svg;
+mod tablebase;
+mod text;
+mod tree;
+mod uci;
+mod versus;
 
 /// A chess board is 8x8 tiles.
 const GRID_SIZE: i16 = 8;
 /// Sutible size of each tile.
 const GRID_CELL_SIZE: (i16, i16) = (90, 90);
 
+/// Directory holding the piece images and the application icon.
+const RESOURCE_DIR: &str = "./resources";
+/// Piece images in the resource directory.
+const SPRITES: [(Piece, &str); 12] = [
+    (Piece::King(Colour::Black), "/black_king.png"),
+    (Piece::Queen(Colour::Black), "/black_queen.png"),
+    (Piece::Rook(Colour::Black), "/black_rook.png"),
+    (Piece::Pawn(Colour::Black), "/black_pawn.png"),
+    (Piece::Bishop(Colour::Black), "/black_bishop.png"),
+    (Piece::Knight(Colour::Black), "/black_knight.png"),
+    (Piece::King(Colour::White), "/white_king.png"),
+    (Piece::Queen(Colour::White), "/white_queen.png"),
+    (Piece::Rook(Colour::White), "/white_rook.png"),
+    (Piece::Pawn(Colour::White), "/white_pawn.png"),
+    (Piece::Bishop(Colour::White), "/white_bishop.png"),
+    (Piece::Knight(Colour::White), "/white_knight.png"),
+];
+
+/// Time the engine opponent gets to think about each move.
+const ENGINE_MOVE_TIME: Duration = Duration::from_secs(1);
+
+/// Number of history rows visible at once in the history viewer.
+const HISTORY_ROWS: usize = 12;
+/// Number of history rows visible while the analysis is shown below them.
+const ANALYSIS_HISTORY_ROWS: usize = 6;
+/// Position and width of the evaluation bar between the board and the side panel, in grid cells.
+const EVAL_BAR_X: f32 = GRID_SIZE as f32 + 0.1;
+const EVAL_BAR_WIDTH: f32 = 0.3;
+/// Top and height of the engine lines below the history viewer, in grid cells.
+const ANALYSIS_Y: f32 = 3.5 + ANALYSIS_HISTORY_ROWS as f32 / 3.0 + 0.1;
+const ANALYSIS_HEIGHT: f32 = TOOLBAR_Y - 0.1 - ANALYSIS_Y;
+/// Most engine lines shown at once.
+const MAX_MULTIPV: usize = 5;
+/// Position and width of the history scrollbar, in grid cells.
+const SCROLLBAR_X: f32 = GRID_SIZE as f32 + 2.25;
+const SCROLLBAR_WIDTH: f32 = 1.0 / 6.0;
+
 /// Size of the application window.
 const SCREEN_SIZE: (f32, f32) = (
     GRID_SIZE as f32 * GRID_CELL_SIZE.0 as f32 + 270f32,
@@ -26,29 +86,119 @@ const HIGHLIGHTED_COLOR: graphics::Color =
     graphics::Color::new(75f32 / 255f32, 148f32 / 255f32, 189f32 / 255f32, 0.8f32);
 const CONTRAST_COLOR: graphics::Color =
     graphics::Color::new(112f32 / 255f32, 78f32 / 255f32, 34f32 / 255f32, 1f32);
+const LAST_MOVE_COLOR: graphics::Color =
+    graphics::Color::new(205f32 / 255f32, 210f32 / 255f32, 106f32 / 255f32, 0.6f32);
+const CHECK_COLOR: graphics::Color =
+    graphics::Color::new(222f32 / 255f32, 49f32 / 255f32, 99f32 / 255f32, 0.6f32);
+const VARIATION_COLOR: graphics::Color =
+    graphics::Color::new(150f32 / 255f32, 108f32 / 255f32, 56f32 / 255f32, 1f32);
+const HINT_COLOR: graphics::Color =
+    graphics::Color::new(60f32 / 255f32, 160f32 / 255f32, 70f32 / 255f32, 0.8f32);
+const THREAT_COLOR: graphics::Color =
+    graphics::Color::new(222f32 / 255f32, 49f32 / 255f32, 99f32 / 255f32, 0.8f32);
 const CERISE: graphics::Color =
     graphics::Color::new(222f32 / 255f32, 49f32 / 255f32, 99f32 / 255f32, 0.15f32);
 
+/// Buttons of the toolbar below the history viewer.
+#[derive(Clone, Copy, PartialEq)]
+enum Button {
+    Undo,
+    Redo,
+    Save,
+    Open,
+}
+
+/// The toolbar buttons from left to right, with their labels.
+const TOOLBAR: [(Button, &str); 4] = [
+    (Button::Undo, "Undo"),
+    (Button::Redo, "Redo"),
+    (Button::Save, "Save"),
+    (Button::Open, "Open"),
+];
+/// Vertical position of the toolbar, in grid cells.
+const TOOLBAR_Y: f32 = 7.6;
+
 /// GUI logic and event implementation structure.
 pub struct AppState {
     pub sprites: Vec<(Piece, graphics::Image)>,
     game: Game, // Save piece positions, which tiles has been clicked, current colour, etc...
     selected_square: Option<(usize, usize)>,
     highlighted_squares: Vec<(usize, usize)>,
-    history: Vec<String>, //A vector containing all previous game states as FEN strings
+    history: MoveTree, // Every move played, including variations, with the FEN strings of the positions after them
     viewing_history: bool,
+    cursor: Path, // Path in the history to the displayed position
+    redo_stack: Vec<Path>, // Positions displayed before each takeback, the moves taken back stay in the history
+    history_scroll: usize, // Index of the first history row shown in the history viewer
+    dragging_scrollbar: bool,
+    message: Option<String>, // Shown on the board until the next click or key press
+    fen_input: Option<String>, // FEN string being typed in, if the FEN entry box is open
+    clipboard: Option<ClipboardContext>, // Kept for the whole session, some platforms clear copied text when it is dropped
+    session_path: path::PathBuf, // File the session is saved to on every move and on exit
+    resume: Option<Session>, // Unfinished game of the last session, offered to be resumed at startup
+    flipped: bool, // Black at the bottom of the board
+    coordinates: bool, // Coordinates drawn along the board edges
+    last_move_shown: bool, // Squares of the displayed move highlighted
+    engine: Option<uci::Engine>, // UCI engine playing against the user, if any
+    level: Option<ai::Level>, // Strength of the built-in computer opponent, if playing against it
+    search: Option<ai::Search>, // Search of the built-in computer running in the background
+    computer_colour: Colour,
+    computer_search: Path, // Position the computer is searching, moves found for other positions are ignored
+    engine_path: Option<path::PathBuf>, // UCI engine binary last played against, also used for analysis
+    analysis: Option<uci::Engine>, // Engine analysing the displayed position, if analysis is on
+    analysis_search: Option<Path>, // Position being analysed
+    analysis_lines: Vec<Option<(uci::Info, Vec<String>)>>, // A slot for every line shown, best first, with the moves in SAN once found
+    multipv: usize, // Number of engine lines shown
+    preview: Option<Game>, // Position at the end of an engine line, shown on the board until the next click or key press
+    hint: Option<hint::Hint>, // Best move and threat for the displayed position, drawn as arrows
+    hint_engine: Option<uci::Engine>, // UCI engine hints are searched with, kept from one hint to the next
+    review: Option<review::Review>, // Scores of the main line, marking moves in the history viewer
+    versus: Option<versus::Match>, // Match between two computer players being played, if any
+    book: Option<book::Book>, // Opening book the computer opponent plays from while in book
+    tablebase: Option<tablebase::Tablebase>, // Endgame tables the computer opponent plays perfectly from
+    probe: Option<tablebase::Probe>, // Result of the displayed position and its moves according to the tables
 }
 
 impl AppState {
     /// Initialise new application, i.e. initialise new game and load resources.
     fn new(ctx: &mut Context) -> GameResult<AppState> {
+        let game = Game::new();
         let state = AppState {
             sprites: AppState::load_sprites(ctx),
-            game: Game::new(),
+            history: MoveTree::new(game.get_fen()),
+            game,
             selected_square: None,
             highlighted_squares: Vec::new(),
-            history: Vec::new(),
             viewing_history: false,
+            cursor: Vec::new(),
+            redo_stack: Vec::new(),
+            history_scroll: 0,
+            dragging_scrollbar: false,
+            message: None,
+            fen_input: None,
+            clipboard: ClipboardContext::new().ok(),
+            session_path: filesystem::user_config_dir(ctx).join("session.pgn"),
+            resume: None,
+            flipped: false,
+            coordinates: true,
+            last_move_shown: true,
+            engine: None,
+            level: None,
+            search: None,
+            computer_colour: Colour::Black,
+            computer_search: Vec::new(),
+            engine_path: None,
+            analysis: None,
+            analysis_search: None,
+            analysis_lines: Vec::new(),
+            multipv: 3,
+            preview: None,
+            hint: None,
+            hint_engine: None,
+            review: None,
+            versus: None,
+            book: None,
+            tablebase: None,
+            probe: None,
         };
 
         Ok(state)
@@ -56,29 +206,822 @@ impl AppState {
 
     /// Loads chess piese images into vector.
     fn load_sprites(ctx: &mut Context) -> Vec<(Piece, graphics::Image)> {
-        [
-            (Piece::King(Colour::Black), "/black_king.png".to_string()),
-            (Piece::Queen(Colour::Black), "/black_queen.png".to_string()),
-            (Piece::Rook(Colour::Black), "/black_rook.png".to_string()),
-            (Piece::Pawn(Colour::Black), "/black_pawn.png".to_string()),
-            (Piece::Bishop(Colour::Black), "/black_bishop.png".to_string()),
-            (Piece::Knight(Colour::Black), "/black_knight.png".to_string()),
-            (Piece::King(Colour::White), "/white_king.png".to_string()),
-            (Piece::Queen(Colour::White), "/white_queen.png".to_string()),
-            (Piece::Rook(Colour::White), "/white_rook.png".to_string()),
-            (Piece::Pawn(Colour::White), "/white_pawn.png".to_string()),
-            (Piece::Bishop(Colour::White), "/white_bishop.png".to_string()),
-            (Piece::Knight(Colour::White), "/white_knight.png".to_string()),
-        ]
+        SPRITES
         .iter()
         .map(|(_piece, _path)| (*_piece, graphics::Image::new(ctx, _path).unwrap()))
         .collect::<Vec<(Piece, graphics::Image)>>()
     }
+
+    /// The move leading up to the displayed position, if any.
+    fn displayed_move(&self) -> Option<&MoveRecord> {
+        self.history.record(&self.cursor)
+    }
+
+    /// Selects a square and highlights the moves of the piece on it, if it is that piece's turn.
+    fn select(&mut self, (rank, file): (usize, usize)) {
+        self.selected_square = Some((rank, file));
+        self.highlighted_squares = Vec::new();
+        if let Some(c) = get_colour(self.game.board[rank][file]) {
+            if c == self.game.current_turn && !self.is_computer(c) {
+                self.highlighted_squares = self.game.board[rank][file].get_valid_moves(
+                    (rank, file),
+                    &self.game.board,
+                    self.game.en_passant_square,
+                    self.game.castlings,
+                    self.game.current_turn,
+                );
+            };
+        };
+    }
+
+    /// Plays a move from the displayed position and records it in the history.
+    /// Moves from a previous position start a new variation.
+    fn play_move(&mut self, from: (usize, usize), to: (usize, usize)) {
+        let before = game_from_fen(&self.game.get_fen());
+        self.game.take_turn(move_to_string(from, to));
+        let record = MoveRecord::new(&before, from, to, &self.game);
+        self.cursor = self.history.add_move(&self.cursor, self.game.get_fen(), record);
+        self.viewing_history = !self.history.is_leaf(&self.cursor);
+        self.redo_stack = Vec::new();
+        self.follow_history();
+        self.save_session();
+        // Games are reviewed once over, by checkmate, stalemate or a draw by the rules
+        let over = versus::game_result(&self.history).is_some();
+        if over && self.cursor.iter().all(|i| *i == 0) && self.versus.is_none() {
+            self.start_review();
+        }
+    }
+
+    /// Saves the session so that it can be resumed after the application is closed.
+    fn save_session(&mut self) {
+        let promotion = promotion_char(self.game.selected_promotion);
+        if let Err(e) = Session::save(&self.session_path, &self.history, &self.cursor, promotion) {
+            self.message = Some(format!("Failed to save session: {}", e));
+        }
+    }
+
+    /// Offers to resume the last session if its game was left unfinished.
+    fn offer_resume(&mut self) {
+        self.resume = Session::load(&self.session_path).filter(|session| session.is_unfinished());
+    }
+
+    /// Continues the game of the last session where it was left.
+    fn resume_session(&mut self) {
+        if let Some(session) = self.resume.take() {
+            self.load(session.history);
+            self.goto(session.cursor);
+            self.game.select_promotion(session.promotion);
+            self.save_session();
+        }
+    }
+
+    /// Checks whether the given colour is played by the computer.
+    /// Takebacks skip over computer moves so that it is a human's turn again.
+    fn is_computer(&self, colour: Colour) -> bool {
+        self.versus.is_some() || ((self.engine.is_some() || self.level.is_some()) && self.computer_colour == colour)
+    }
+
+    /// Name of the computer opponent, if there is one.
+    fn computer_name(&self) -> Option<String> {
+        match (&self.engine, self.level) {
+            (Some(engine), _) => Some(engine.name.clone()),
+            (None, Some(level)) => Some(format!("Computer ({})", level.name)),
+            (None, None) => None,
+        }
+    }
+
+    /// Checks whether the computer opponent is searching for a move.
+    fn is_thinking(&self) -> bool {
+        self.engine.as_ref().is_some_and(|engine| engine.is_searching()) || self.search.is_some()
+    }
+
+    /// Starts a match between two computer players, replacing any computer opponent.
+    /// The games start from the position the current game started from.
+    fn start_match(&mut self, players: [versus::Player; 2], games: usize, path: path::PathBuf) {
+        match versus::Match::start(players, games, path) {
+            Ok(versus) => {
+                self.engine = None;
+                self.level = None;
+                self.search = None;
+                self.resume = None;
+                self.load(MoveTree::new(self.history.start_fen.clone()));
+                let (white, black) = versus.names();
+                self.message = Some(format!("Match of {} games: {} against {}", games, white, black));
+                self.versus = Some(versus);
+            }
+            Err(e) => self.message = Some(format!("Failed to start match: {}", e)),
+        }
+    }
+
+    /// Plays the moves of the match as they are found, and starts the next game once one is over.
+    /// The board is brought back to the game being played whenever a move is made.
+    fn update_match(&mut self) {
+        let mut versus = match self.versus.take() {
+            Some(versus) => versus,
+            None => return,
+        };
+        if let Some(result) = versus::game_result(&self.history) {
+            if versus.finish_game(&self.history, result) {
+                self.end_match(&versus, "Match over");
+                return;
+            }
+            self.load(MoveTree::new(self.history.start_fen.clone()));
+            self.message = Some(format!("Game {} ended {}, {}", versus.round() - 1, result, versus.summary()));
+            self.versus = Some(versus);
+            return;
+        }
+        match versus.think(&self.history) {
+            Some(Ok((from, to, promotion))) => {
+                let end = self.history.line_end(&[]);
+                if self.cursor != end {
+                    self.goto(end);
+                }
+                if !legal_moves(&self.game).contains(&(from, to)) {
+                    let (from, to) = (square_to_string(from), square_to_string(to));
+                    self.end_match(&versus, &format!("Match stopped, illegal move {}{}", from, to));
+                    return;
+                }
+                self.versus = Some(versus);
+                self.play_move_promoting(from, to, promotion);
+            }
+            Some(Err(e)) => self.end_match(&versus, &format!("Match stopped, {}", e)),
+            None => self.versus = Some(versus),
+        }
+    }
+
+    /// Writes the games of the match to its PGN file and shows the final score.
+    fn end_match(&mut self, versus: &versus::Match, reason: &str) {
+        self.message = Some(match versus.save() {
+            Ok(()) => format!("{}: {}, saved to {}", reason, versus.summary(), versus.path.display()),
+            Err(e) => format!("{}: {}, failed to save: {}", reason, versus.summary(), e),
+        });
+    }
+
+    /// Tells who the computer opponent is and which side it plays.
+    fn announce_computer(&mut self) {
+        if let Some(name) = self.computer_name() {
+            self.message = Some(format!("{} plays {:?}", name, self.computer_colour));
+        }
+    }
+
+    /// Starts playing against the UCI engine binary at the given path, instead of the built-in computer.
+    fn start_engine(&mut self, path: &path::Path) {
+        match uci::Engine::start(path) {
+            Ok(engine) => {
+                self.engine = Some(engine);
+                self.engine_path = Some(path.to_path_buf());
+                self.hint_engine = None;
+                self.level = None;
+                self.search = None;
+                self.announce_computer();
+            }
+            Err(e) => self.message = Some(format!("Failed to start engine: {}", e)),
+        }
+    }
+
+    /// Asks for a UCI engine binary to play against, or stops playing against the current one.
+    fn choose_engine(&mut self) {
+        if self.engine.take().is_some() {
+            self.message = Some("Stopped playing against the engine".to_string());
+        } else if let Some(path) = rfd::FileDialog::new().pick_file() {
+            self.start_engine(&path);
+        }
+    }
+
+    /// Switches the built-in computer opponent to the next stronger level, the strongest level switches it off.
+    /// The built-in computer replaces any UCI engine.
+    fn next_level(&mut self) {
+        let next = match self.level {
+            None => ai::LEVELS.first(),
+            Some(level) => ai::LEVELS
+                .iter()
+                .position(|l| l.name == level.name)
+                .and_then(|i| ai::LEVELS.get(i + 1)),
+        };
+        self.level = next.copied();
+        self.engine = None;
+        self.search = None;
+        if self.level.is_some() {
+            self.announce_computer();
+        } else {
+            self.message = Some("Stopped playing against the computer".to_string());
+        }
+    }
+
+    /// Lets the computer play the other side.
+    fn swap_sides(&mut self) {
+        self.computer_colour = opponent(self.computer_colour);
+        self.selected_square = None;
+        self.highlighted_squares = Vec::new();
+        self.announce_computer();
+    }
+
+    /// Handles what the computer opponent has found and lets it think when it is its turn.
+    /// A search is stopped as soon as another position is displayed.
+    fn update_computer(&mut self) {
+        if self.computer_search != self.cursor {
+            self.search = None;
+            if let Some(engine) = &mut self.engine {
+                engine.stop();
+            }
+        }
+
+        if let Some((from, to, promotion)) = self.search.as_ref().and_then(|search| search.poll()) {
+            self.search = None;
+            self.play_move_promoting(from, to, promotion);
+        }
+
+        let playing = self.engine.is_some();
+        let events = self.engine.as_mut().map_or(Vec::new(), |engine| engine.poll());
+        for event in events {
+            match event {
+                uci::Event::BestMove(bestmove) => {
+                    let legal = legal_moves(&self.game);
+                    match uci::uci_to_move(&bestmove).filter(|(from, to, _promotion)| legal.contains(&(*from, *to))) {
+                        Some((from, to, promotion)) => self.play_move_promoting(from, to, promotion),
+                        None => {
+                            self.engine = None;
+                            self.message = Some(format!("The engine played the illegal move {}", bestmove));
+                        }
+                    }
+                }
+                uci::Event::Crashed => {
+                    self.engine = None;
+                    self.message = Some("The engine crashed".to_string());
+                }
+                uci::Event::TimedOut => {
+                    self.engine = None;
+                    self.message = Some("The engine stopped answering".to_string());
+                }
+                uci::Event::Info(_info) => (),
+            }
+        }
+        // An engine dropped out of the game resigns it, which ends the game as much as a checkmate does
+        if playing && self.engine.is_none() {
+            let reason = self.message.take().unwrap_or_default();
+            self.start_review();
+            if self.review.is_some() {
+                self.message = Some(format!("{}, reviewing the game...", reason));
+            } else {
+                self.message = Some(reason);
+            }
+        }
+
+        // Matches are driven by update_match, every move coming from the contestants
+        if self.versus.is_some() {
+            return;
+        }
+        if !self.is_thinking()
+            && !self.viewing_history
+            && self.resume.is_none()
+            && self.is_computer(self.game.current_turn)
+            && !legal_moves(&self.game).is_empty()
+        {
+            if let Some((from, to, promotion)) = book_move(self.book.as_ref(), self.versus.as_ref(), &self.game) {
+                self.play_move_promoting(from, to, promotion);
+                return;
+            }
+            // The probe of the displayed position is shared with the tablebase panel
+            self.update_probe();
+            let perfect = self.probe.as_ref().and_then(|probe| probe.best_moves().first().copied());
+            if let Some((from, to, promotion)) = perfect {
+                self.play_move_promoting(from, to, promotion);
+                return;
+            }
+            self.computer_search = self.cursor.clone();
+            let moves = self.uci_moves();
+            if let Some(engine) = &mut self.engine {
+                engine.go(&self.history.start_fen, &moves, ENGINE_MOVE_TIME);
+            } else if let Some(level) = self.level {
+                self.search = Some(ai::Search::start(self.game.get_fen(), level));
+            }
+        }
+    }
+
+    /// The book moves of the displayed position with their weights, heaviest first.
+    fn book_moves(&self) -> Vec<(san::SanMove, u16)> {
+        self.book.as_ref().map_or(Vec::new(), |book| book.moves(&self.game))
+    }
+
+    /// Probes the endgame tables for the displayed position whenever another position is shown.
+    fn update_probe(&mut self) {
+        let fen = self.game.get_fen();
+        if self.probe.as_ref().is_some_and(|probe| probe.fen == fen) {
+            return;
+        }
+        self.probe = self.tablebase.as_ref().and_then(|tablebase| tablebase.probe(&fen));
+    }
+
+    /// The moves leading up to the displayed position in UCI notation, as engines are given them.
+    fn uci_moves(&self) -> Vec<String> {
+        self.history
+            .line(&self.cursor)
+            .iter()
+            .map(|node| uci::move_to_uci(&node.record))
+            .collect()
+    }
+
+    /// Switches live analysis of the displayed position on or off.
+    /// Analysis uses the UCI engine last played against, or asks for one.
+    fn toggle_analysis(&mut self) {
+        if self.analysis.take().is_some() {
+            self.analysis_lines = Vec::new();
+            self.message = Some("Stopped analysis".to_string());
+        } else if let Some(path) = self.engine_path.clone().or_else(|| rfd::FileDialog::new().pick_file()) {
+            match uci::Engine::start(&path) {
+                Ok(engine) => {
+                    self.analysis = Some(engine);
+                    self.analysis_search = None;
+                    self.engine_path = Some(path);
+                    self.hint_engine = None;
+                }
+                Err(e) => self.message = Some(format!("Failed to start engine: {}", e)),
+            }
+        }
+        // The history viewer has a different height with and without analysis
+        self.history_scroll = self.history_scroll.min(self.max_history_scroll());
+        self.follow_history();
+    }
+
+    /// Restarts the analysis whenever another position is displayed and keeps the best line found.
+    fn update_analysis(&mut self) {
+        if self.analysis.is_none() {
+            return;
+        }
+
+        if self.analysis_search.as_ref() != Some(&self.cursor) {
+            let moves = self.uci_moves();
+            let finished = legal_moves(&self.game).is_empty();
+            if let Some(engine) = &mut self.analysis {
+                engine.stop();
+                if !finished {
+                    engine.set_option("MultiPV", &self.multipv.to_string());
+                    engine.analyse(&self.history.start_fen, &moves);
+                }
+            }
+            self.analysis_search = Some(self.cursor.clone());
+            self.analysis_lines = Vec::new();
+            self.analysis_lines.resize_with(self.multipv, || None);
+        }
+
+        let events = self.analysis.as_mut().map_or(Vec::new(), |engine| engine.poll());
+        for event in events {
+            match event {
+                // Lines are numbered from 1, and a line may be reported before the ones above it
+                uci::Event::Info(info) if (1..=self.analysis_lines.len()).contains(&info.multipv) => {
+                    let moves: Vec<san::SanMove> = info.pv.iter().map_while(|m| uci::uci_to_move(m)).collect();
+                    let line = san::variation(&self.game.get_fen(), &moves);
+                    let index = info.multipv - 1;
+                    self.analysis_lines[index] = Some((info, line));
+                }
+                uci::Event::Crashed => {
+                    self.analysis = None;
+                    self.analysis_lines = Vec::new();
+                    self.message = Some("The analysis engine crashed".to_string());
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// The evaluation of the displayed position by the analysis, from white's side.
+    fn evaluation(&self) -> Option<uci::Score> {
+        self.analysis_lines
+            .first()
+            .and_then(Option::as_ref)
+            .map(|(info, _line)| info.score.for_white(self.game.current_turn))
+    }
+
+    /// Changes the number of engine lines shown and restarts the analysis with it.
+    fn change_multipv(&mut self, change: i32) {
+        self.multipv = (self.multipv as i32 + change).clamp(1, MAX_MULTIPV as i32) as usize;
+        self.analysis_search = None;
+        self.message = Some(format!("Showing {} engine lines", self.multipv));
+    }
+
+    /// The moves of an engine line that are legal from the displayed position, in order.
+    fn engine_line(&self, index: usize) -> Vec<san::SanMove> {
+        let mut game = game_from_fen(&self.game.get_fen());
+        let mut moves = Vec::new();
+        let pv = self
+            .analysis_lines
+            .get(index)
+            .and_then(Option::as_ref)
+            .map_or(&[][..], |(info, _line)| &info.pv[..]);
+        for (from, to, promotion) in pv.iter().map_while(|m| uci::uci_to_move(m)) {
+            if !legal_moves(&game).contains(&(from, to)) {
+                break;
+            }
+            game.select_promotion(promotion.unwrap_or('q'));
+            game.take_turn(move_to_string(from, to));
+            moves.push((from, to, promotion));
+        }
+        moves
+    }
+
+    /// Plays the first move of an engine line, unless it is the computer's turn.
+    fn play_engine_line(&mut self, index: usize) {
+        if let Some((from, to, promotion)) = self.engine_line(index).first().copied() {
+            if !self.is_computer(self.game.current_turn) {
+                self.play_move_promoting(from, to, promotion);
+            }
+        }
+    }
+
+    /// Shows the position at the end of an engine line on the board until the next click or key press.
+    fn preview_engine_line(&mut self, index: usize) {
+        let moves = self.engine_line(index);
+        if moves.is_empty() {
+            return;
+        }
+        let mut game = game_from_fen(&self.game.get_fen());
+        for (from, to, promotion) in &moves {
+            game.select_promotion(promotion.unwrap_or('q'));
+            game.take_turn(move_to_string(*from, *to));
+        }
+        self.preview = Some(game);
+        self.selected_square = None;
+        self.highlighted_squares = Vec::new();
+        self.message = Some(format!("Previewing line {}, click to return", index + 1));
+    }
+
+    /// Starts looking for the best move and the threat in the displayed position, replacing any earlier hint.
+    /// The UCI engine last chosen is used if there is one, otherwise the built-in computer
+    /// at the level played against, or its strongest level.
+    fn ask_hint(&mut self) {
+        if legal_moves(&self.game).is_empty() {
+            return;
+        }
+        self.drop_hint();
+        if let (None, Some(path)) = (&self.hint_engine, &self.engine_path) {
+            match uci::Engine::start(path) {
+                Ok(engine) => self.hint_engine = Some(engine),
+                Err(e) => {
+                    self.message = Some(format!("Failed to start engine: {}", e));
+                    return;
+                }
+            }
+        }
+        let level = self.level.unwrap_or(ai::LEVELS[ai::LEVELS.len() - 1]);
+        self.hint = Some(hint::Hint::start(&self.history, self.cursor.clone(), self.hint_engine.as_mut(), level));
+        self.message = Some("Looking for a hint...".to_string());
+    }
+
+    /// Drops the hint and stops the engine searching for it.
+    fn drop_hint(&mut self) {
+        if self.hint.take().is_some() {
+            if let Some(engine) = &mut self.hint_engine {
+                engine.stop();
+            }
+        }
+    }
+
+    /// Fills in the hint as its moves are found. Hints for positions no longer displayed are dropped.
+    fn update_hint(&mut self) {
+        if self.hint.as_ref().is_some_and(|hint| hint.path != self.cursor) {
+            self.drop_hint();
+        }
+        if let Some(hint) = &mut self.hint {
+            let searching = hint.is_searching();
+            if let Err(e) = hint.update(self.hint_engine.as_mut()) {
+                self.drop_hint();
+                self.hint_engine = None;
+                self.message = Some(format!("No hint: {}", e));
+            } else if searching && !hint.is_searching() {
+                self.message = None;
+            }
+        }
+    }
+
+    /// Starts reviewing the main line of the game, with the UCI engine last chosen if any.
+    fn start_review(&mut self) {
+        if self.history.main_line().is_empty() {
+            return;
+        }
+        self.review = Some(review::Review::start(&self.history, self.engine_path.clone()));
+        self.message = Some("Reviewing the game...".to_string());
+    }
+
+    /// Collects the scores of the review and reports the accuracy of both players once it is finished.
+    fn update_review(&mut self) {
+        if let Some(review) = &mut self.review {
+            let finished = review.is_finished();
+            if let Err(e) = review.update() {
+                self.review = None;
+                self.message = Some(format!("Review failed: {}", e));
+            } else if !finished && review.is_finished() {
+                self.message = Some(format!(
+                    "Accuracy: white {:.1}%, black {:.1}%",
+                    review.accuracy(Colour::White).unwrap_or(100.0),
+                    review.accuracy(Colour::Black).unwrap_or(100.0),
+                ));
+            }
+        }
+    }
+
+    /// Plays a move with the given promotion piece, leaving the user's promotion choice as it was.
+    fn play_move_promoting(&mut self, from: (usize, usize), to: (usize, usize), promotion: Option<char>) {
+        let selected = promotion_char(self.game.selected_promotion);
+        self.game.select_promotion(promotion.unwrap_or('q'));
+        self.play_move(from, to);
+        self.game.select_promotion(selected);
+        self.selected_square = None;
+        self.highlighted_squares = Vec::new();
+    }
+
+    /// Checks whether there is a move to take back.
+    fn can_undo(&self) -> bool {
+        !self.cursor.is_empty() && self.versus.is_none()
+    }
+
+    /// Takes back the last move, and also the move before it if that was made by the computer.
+    /// The moves stay in the history, so that the tree keeps its order and a different move starts a variation.
+    /// The piece that made the first of the taken back moves is selected again.
+    fn undo(&mut self) {
+        if !self.can_undo() {
+            return;
+        }
+
+        let before = self.cursor.clone();
+        let mut path = self.cursor.clone();
+        let mut from = None;
+        while let Some(record) = self.history.record(&path) {
+            let computer = self.is_computer(record.colour());
+            from = Some(record.from);
+            path.pop();
+            if !computer {
+                break;
+            }
+        }
+
+        self.goto(path);
+        if let Some(square) = from {
+            self.select(square);
+        }
+        self.redo_stack.push(before);
+        self.save_session();
+    }
+
+    /// Shows the position before the last takeback again.
+    fn redo(&mut self) {
+        if let Some(path) = self.redo_stack.pop().filter(|path| self.history.node(path).is_some()) {
+            self.goto(path);
+            self.save_session();
+        }
+    }
+
+    /// Checks whether a toolbar button currently does anything.
+    fn is_enabled(&self, button: Button) -> bool {
+        match button {
+            Button::Undo => self.can_undo(),
+            Button::Redo => !self.redo_stack.is_empty(),
+            Button::Save => true,
+            Button::Open => self.versus.is_none(),
+        }
+    }
+
+    /// Performs the action of a toolbar button, if it is enabled.
+    fn press(&mut self, button: Button) {
+        if !self.is_enabled(button) {
+            return;
+        }
+        match button {
+            Button::Undo => self.undo(),
+            Button::Redo => self.redo(),
+            Button::Save => self.save_pgn(),
+            Button::Open => self.open_pgn(),
+        }
+    }
+
+    /// Replaces the history with a loaded game, displays its final position and saves the session.
+    fn load(&mut self, history: MoveTree) {
+        self.history = history;
+        self.redo_stack = Vec::new();
+        self.history_scroll = 0;
+        self.review = None;
+        self.goto(self.history.line_end(&[]));
+        self.save_session();
+    }
+
+    /// Starts a new game from the position given by a FEN string.
+    /// Malformed FEN strings are reported in a message instead of loaded.
+    fn load_fen(&mut self, fen: &str) {
+        match fen::validate(fen.trim()) {
+            Ok(()) => self.load(MoveTree::new(game_from_fen(fen.trim()).get_fen())),
+            Err(e) => self.message = Some(format!("Invalid FEN: {}", e)),
+        }
+    }
+
+    /// Loads a PGN file, showing a message with the line and column of any error in it.
+    fn load_pgn(&mut self, path: &path::Path) {
+        let loaded = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| pgn::read(&text).map_err(|e| e.to_string()));
+        match loaded {
+            Ok(history) => {
+                self.load(history);
+                self.message = Some(format!("Loaded {}", path.display()));
+            }
+            Err(e) => self.message = Some(format!("Failed to load game: {}", e)),
+        }
+    }
+
+    /// Asks for a PGN file and loads it.
+    fn open_pgn(&mut self) {
+        let path = rfd::FileDialog::new()
+            .add_filter("PGN", &["pgn"])
+            .pick_file();
+        if let Some(path) = path {
+            self.load_pgn(&path);
+        }
+    }
+
+    /// Copies text to the clipboard and tells what was copied in a message.
+    fn copy(&mut self, text: String, what: &str) {
+        let copied = match &mut self.clipboard {
+            Some(clipboard) => clipboard.set_contents(text).map_err(|e| e.to_string()),
+            None => Err("No clipboard available".to_string()),
+        };
+        self.message = Some(match copied {
+            Ok(()) => format!("Copied {}", what),
+            Err(e) => format!("Failed to copy {}: {}", what, e),
+        });
+    }
+
+    /// The text on the clipboard, or a message telling why there is none.
+    fn clipboard_text(&mut self) -> Option<String> {
+        let text = match &mut self.clipboard {
+            Some(clipboard) => clipboard.get_contents().map_err(|e| e.to_string()),
+            None => Err("No clipboard available".to_string()),
+        };
+        match text {
+            Ok(text) => Some(text),
+            Err(e) => {
+                self.message = Some(format!("Failed to paste: {}", e));
+                None
+            }
+        }
+    }
+
+    /// Loads the FEN string or PGN game on the clipboard.
+    fn paste(&mut self) {
+        if let Some(text) = self.clipboard_text() {
+            if fen::validate(text.trim()).is_ok() {
+                self.load_fen(&text);
+            } else {
+                match pgn::read(&text) {
+                    Ok(history) => {
+                        self.load(history);
+                        self.message = Some("Pasted game".to_string());
+                    }
+                    Err(e) => self.message = Some(format!("Clipboard holds no FEN or PGN: {}", e)),
+                }
+            }
+        }
+    }
+
+    /// Asks for a file name and saves the main line of the game to it as PGN.
+    fn save_pgn(&mut self) {
+        let path = rfd::FileDialog::new()
+            .add_filter("PGN", &["pgn"])
+            .set_file_name("game.pgn")
+            .save_file();
+        if let Some(path) = path {
+            let pgn = pgn::write(&self.history, pgn::result(&self.history));
+            self.message = Some(match fs::write(&path, pgn) {
+                Ok(()) => format!("Saved {}", path.display()),
+                Err(e) => format!("Failed to save game: {}", e),
+            });
+        }
+    }
+
+    /// Asks for a file name and saves the displayed position to it as an SVG diagram, as it is shown on the board.
+    fn export_svg(&mut self) {
+        let path = rfd::FileDialog::new()
+            .add_filter("SVG", &["svg"])
+            .set_file_name("position.svg")
+            .save_file();
+        if let Some(path) = path {
+            let diagram = svg::Diagram {
+                flipped: self.flipped,
+                coordinates: self.coordinates,
+                last_move: self
+                    .displayed_move()
+                    .filter(|_record| self.last_move_shown)
+                    .map(|record| (record.from, record.to)),
+                selected: self.selected_square,
+            };
+            self.message = Some(match fs::write(&path, svg::board(&self.game.board, &diagram)) {
+                Ok(()) => format!("Saved {}", path.display()),
+                Err(e) => format!("Failed to save diagram: {}", e),
+            });
+        }
+    }
+
+    /// Asks for a file name and saves the main line of the game to it as an animated GIF.
+    fn export_gif(&mut self) {
+        let path = rfd::FileDialog::new()
+            .add_filter("GIF", &["gif"])
+            .set_file_name("game.gif")
+            .save_file();
+        if let Some(path) = path {
+            let animation = animation::Animation {
+                flipped: self.flipped,
+                ..animation::Animation::default()
+            };
+            self.message = Some(match animation.write(&self.history, &path) {
+                Ok(()) => format!("Saved {}", path.display()),
+                Err(e) => format!("Failed to save animation: {}", e),
+            });
+        }
+    }
+
+    /// Displays the position at the end of the path in the history.
+    fn goto(&mut self, path: Path) {
+        self.game.set_state_from_fen(self.history.fen(&path));
+        self.viewing_history = !self.history.is_leaf(&path);
+        self.cursor = path;
+        self.selected_square = None;
+        self.highlighted_squares = Vec::new();
+        self.follow_history();
+    }
+
+    /// Displays the position the given number of moves before the displayed one.
+    fn step_back(&mut self, moves: usize) {
+        let mut path = self.cursor.clone();
+        path.truncate(path.len().saturating_sub(moves));
+        self.goto(path);
+    }
+
+    /// Displays the position the given number of moves after the displayed one, following main continuations.
+    fn step_forward(&mut self, moves: usize) {
+        let mut path = self.cursor.clone();
+        for _ in 0..moves {
+            if !self.history.is_leaf(&path) {
+                path.push(0);
+            }
+        }
+        self.goto(path);
+    }
+
+    /// Number of rows needed to show the whole history, two entries per row.
+    fn history_rows(&self) -> usize {
+        self.history.rows().len()
+    }
+
+    /// Number of history rows shown at once, fewer while the analysis is shown below them.
+    fn visible_history_rows(&self) -> usize {
+        if self.analysis.is_some() || self.probe.is_some() || !self.book_moves().is_empty() {
+            ANALYSIS_HISTORY_ROWS
+        } else {
+            HISTORY_ROWS
+        }
+    }
+
+    /// Largest possible scroll offset of the history viewer.
+    fn max_history_scroll(&self) -> usize {
+        self.history_rows().saturating_sub(self.visible_history_rows())
+    }
+
+    /// Scrolls the history viewer by the given number of rows, clamped to the history length.
+    fn scroll_history(&mut self, rows: i32) {
+        let scroll = self.history_scroll as i32 + rows;
+        self.history_scroll = (scroll.max(0) as usize).min(self.max_history_scroll());
+    }
+
+    /// Scrolls the history viewer so that the displayed move is visible.
+    fn follow_history(&mut self) {
+        let row = self
+            .history
+            .rows()
+            .iter()
+            .position(|row| row.cells.contains(&Some(self.cursor.clone())))
+            .unwrap_or(0);
+        if row < self.history_scroll {
+            self.history_scroll = row;
+        } else if row >= self.history_scroll + self.visible_history_rows() {
+            self.history_scroll = row + 1 - self.visible_history_rows();
+        }
+    }
+
+    /// Scrolls the history viewer so that the scrollbar thumb is centered at screen height y.
+    fn drag_scrollbar(&mut self, y: f32) {
+        let visible = self.visible_history_rows() as f32;
+        let fraction = (y / GRID_CELL_SIZE.1 as f32 - 3.5) / visible * 3.0;
+        let scroll = fraction * self.history_rows() as f32 - visible / 2.0;
+        self.history_scroll = (scroll.round().max(0.0) as usize).min(self.max_history_scroll());
+    }
 }
 
 impl event::EventHandler<GameError> for AppState {
     /// For updating game logic, which front-end doesn't handle.
     fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        self.update_computer();
+        self.update_analysis();
+        self.update_hint();
+        self.update_review();
+        self.update_match();
+        self.update_probe();
         Ok(())
     }
 
@@ -88,8 +1031,17 @@ impl event::EventHandler<GameError> for AppState {
         graphics::clear(ctx, CONTRAST_COLOR);
 
         draw::board(&self, ctx);
+        draw::hint(self, ctx);
+        draw::eval_bar(self, ctx);
         draw::promotion_selector(&self, ctx);
         draw::history(&self, ctx);
+        draw::analysis(self, ctx);
+        draw::book(self, ctx);
+        draw::tablebase(self, ctx);
+        draw::toolbar(self, ctx);
+        draw::message(self, ctx);
+        draw::fen_input(self, ctx);
+        draw::resume_prompt(self, ctx);
         draw::info_text(&self, ctx);
 
         if self.viewing_history { // Move to function change to text on screen? Make text for if game over as well.
@@ -118,58 +1070,44 @@ impl event::EventHandler<GameError> for AppState {
         x: f32,
         y: f32,
     ) {
-        if button == event::MouseButton::Left {
+        self.message = None;
+        if self.preview.take().is_some() {
+            return;
+        }
+        let in_analysis = self.analysis.is_some()
+            && x > ((GRID_SIZE as f32 + 0.5f32) * GRID_CELL_SIZE.0 as f32)
+            && x < ((GRID_SIZE as f32 + 2.5f32) * GRID_CELL_SIZE.0 as f32)
+            && y > ANALYSIS_Y * GRID_CELL_SIZE.1 as f32
+            && y < (ANALYSIS_Y + ANALYSIS_HEIGHT) * GRID_CELL_SIZE.1 as f32;
+        let line = ((y / 90f32 - ANALYSIS_Y) / (ANALYSIS_HEIGHT / self.multipv as f32)).floor() as usize;
+        if in_analysis && button == event::MouseButton::Right {
+            self.preview_engine_line(line);
+        } else if in_analysis && button == event::MouseButton::Left {
+            self.play_engine_line(line);
+        } else if button == event::MouseButton::Left {
             /* check click position and update board accordingly */
             if x < (GRID_SIZE as f32 * GRID_CELL_SIZE.0 as f32) {
-                let rank = (y / 90f32).floor() as usize;
-                let file = (x / 90f32).floor() as usize;
+                let (rank, file) = flip_square(
+                    ((y / 90f32).floor() as usize, (x / 90f32).floor() as usize),
+                    self.flipped,
+                );
 
                 match self.selected_square {
                     Some(t) => {
                         if self.highlighted_squares.contains(&(rank, file)) {
-                            if !self.viewing_history {
-                                self.history.push(self.game.get_fen());
-                                self.game
-                                    .take_turn(move_to_string((t.0, t.1), (rank, file)));
-                            }
+                            self.play_move((t.0, t.1), (rank, file));
                             self.selected_square = None;
                             self.highlighted_squares = Vec::new();
                         } else if (rank, file) == t {
                             self.selected_square = None;
                             self.highlighted_squares = Vec::new();
                         } else {
-                            self.selected_square = Some((rank, file));
-                            self.highlighted_squares = Vec::new();
-                            if let Some(c) = get_colour(self.game.board[rank][file]) {
-                                if c == self.game.current_turn {
-                                    self.highlighted_squares = self.game.board[rank][file]
-                                        .get_valid_moves(
-                                            (rank, file),
-                                            &self.game.board,
-                                            self.game.en_passant_square,
-                                            self.game.castlings,
-                                            self.game.current_turn,
-                                        );
-                                };
-                            };
+                            self.select((rank, file));
                         }
                     }
                     None => {
                         // Tuple (rank, file)
-                        self.selected_square = Some((rank, file));
-                        self.highlighted_squares = Vec::new();
-                        if let Some(c) = get_colour(self.game.board[rank][file]) {
-                            if c == self.game.current_turn {
-                                self.highlighted_squares = self.game.board[rank][file]
-                                    .get_valid_moves(
-                                        (rank, file),
-                                        &self.game.board,
-                                        self.game.en_passant_square,
-                                        self.game.castlings,
-                                        self.game.current_turn,
-                                    );
-                            };
-                        };
+                        self.select((rank, file));
                     }
                 }
             } else if x > ((GRID_SIZE as f32 + 0.5f32) * GRID_CELL_SIZE.0 as f32)
@@ -189,74 +1127,384 @@ impl event::EventHandler<GameError> for AppState {
             } else if x > ((GRID_SIZE as f32 + 0.5f32 + 1f32 / 3f32) * GRID_CELL_SIZE.0 as f32)
                 && x < ((GRID_SIZE as f32 + 0.5f32 + 5f32 / 3f32) * GRID_CELL_SIZE.0 as f32)
                 && y > GRID_CELL_SIZE.1 as f32 * 3.5f32
-                && y < (3.5f32 + 4f32) * GRID_CELL_SIZE.1 as f32
+                && y < (3.5f32 + self.visible_history_rows() as f32 / 3f32) * GRID_CELL_SIZE.1 as f32
             {
-                let rank = (y / 90f32 * 3f32 - 10.5f32).floor() as usize;
+                let row = (y / 90f32 * 3f32 - 10.5f32).floor() as usize + self.history_scroll;
                 let file = (x / 90f32 - 8.5f32).floor() as usize;
-                let index: usize = match (rank, file) {
-                    (0, 0) => 1,
-                    (0, 1) => 2,
-                    (1, 0) => 3,
-                    (1, 1) => 4,
-                    (2, 0) => 5,
-                    (2, 1) => 6,
-                    (3, 0) => 7,
-                    (3, 1) => 8,
-                    (4, 0) => 9,
-                    (4, 1) => 10,
-                    (5, 0) => 11,
-                    (5, 1) => 12,
-                    (6, 0) => 13,
-                    (6, 1) => 14,
-                    (7, 0) => 15,
-                    (7, 1) => 16,
-                    (8, 0) => 17,
-                    (8, 1) => 18,
-                    (9, 0) => 19,
-                    (9, 1) => 20,
-                    (10, 0) => 21,
-                    (10, 1) => 22,
-                    (11, 0) => 23,
-                    (11, 1) => 24,
-                    _ => panic!("Out of bounds!"),
-                };
-
-                if index <= self.history.len() && !self.viewing_history {
-                    self.history.push(self.game.get_fen());
-                    self.game.set_state_from_fen(&self.history[index - 1]);
-                    self.viewing_history = true;
-                } else if index < self.history.len() && self.viewing_history {
-                    self.game.set_state_from_fen(&self.history[index - 1]);
-                } else if index == self.history.len() && self.viewing_history {
-                    if let Some(current_turn) = self.history.pop() {
-                        self.game.set_state_from_fen(&current_turn);
-                        self.viewing_history = false;
-                    }
+
+                // Clicking a move shows the position after it
+                let rows = self.history.rows();
+                if let Some(path) = rows.get(row).and_then(|r| r.cells.get(file).cloned().flatten()) {
+                    self.goto(path);
                 }
+            } else if x > ((GRID_SIZE as f32 + 0.5f32) * GRID_CELL_SIZE.0 as f32)
+                && x < ((GRID_SIZE as f32 + 2.5f32) * GRID_CELL_SIZE.0 as f32)
+                && y > TOOLBAR_Y * GRID_CELL_SIZE.1 as f32
+                && y < (TOOLBAR_Y + 1f32 / 3f32) * GRID_CELL_SIZE.1 as f32
+            {
+                let width = 2f32 / TOOLBAR.len() as f32;
+                let index = ((x / 90f32 - 8.5f32) / width).floor() as usize;
+                if let Some((button, _label)) = TOOLBAR.get(index) {
+                    self.press(*button);
+                }
+            } else if x > SCROLLBAR_X * GRID_CELL_SIZE.0 as f32
+                && x < (SCROLLBAR_X + SCROLLBAR_WIDTH) * GRID_CELL_SIZE.0 as f32
+                && y > GRID_CELL_SIZE.1 as f32 * 3.5f32
+                && y < (3.5f32 + self.visible_history_rows() as f32 / 3f32) * GRID_CELL_SIZE.1 as f32
+            {
+                self.dragging_scrollbar = true;
+                self.drag_scrollbar(y);
             }
         }
     }
+
+    /// Stop dragging the history scrollbar
+    fn mouse_button_up_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: event::MouseButton,
+        _x: f32,
+        _y: f32,
+    ) {
+        if button == event::MouseButton::Left {
+            self.dragging_scrollbar = false;
+        }
+    }
+
+    /// Move the history scrollbar while it is dragged
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, _x: f32, y: f32, _dx: f32, _dy: f32) {
+        if self.dragging_scrollbar {
+            self.drag_scrollbar(y);
+        }
+    }
+
+    /// Scroll the history viewer with the mouse wheel
+    fn mouse_wheel_event(&mut self, _ctx: &mut Context, _x: f32, y: f32) {
+        if y > 0f32 {
+            self.scroll_history(-1);
+        } else if y < 0f32 {
+            self.scroll_history(1);
+        }
+    }
+
     fn key_down_event(
         &mut self,
         ctx: &mut Context,
         keycode: event::KeyCode,
-        _keymods: event::KeyMods,
+        keymods: event::KeyMods,
         _repeat: bool,
     ) {
+        let ctrl = keymods.contains(event::KeyMods::CTRL);
+        self.message = None;
+        self.preview = None;
+
+        // While the FEN entry box is open keys edit the FEN string instead
+        if self.fen_input.is_some() && ctrl && keycode == event::KeyCode::V {
+            let text = self.clipboard_text().unwrap_or_default();
+            if let Some(input) = &mut self.fen_input {
+                input.push_str(text.trim());
+            }
+            return;
+        }
+        if let Some(input) = &mut self.fen_input {
+            if keycode == event::KeyCode::Back {
+                input.pop();
+            } else if keycode == event::KeyCode::Return {
+                if let Some(fen) = self.fen_input.take() {
+                    self.load_fen(&fen);
+                }
+            } else if keycode == event::KeyCode::Escape {
+                self.fen_input = None;
+            }
+            return;
+        }
+
+        // While the last session is offered to be resumed only its answer keys work
+        if self.resume.is_some() {
+            if keycode == event::KeyCode::Y {
+                self.resume_session();
+            } else if keycode == event::KeyCode::N || keycode == event::KeyCode::Escape {
+                self.resume = None;
+            }
+            return;
+        }
+
+        // During a match the history is the game being played, which only the players change
+        let changes_history = match keycode {
+            event::KeyCode::O | event::KeyCode::V | event::KeyCode::F | event::KeyCode::K | event::KeyCode::L => ctrl,
+            event::KeyCode::R => true,
+            event::KeyCode::P | event::KeyCode::Delete => !ctrl,
+            _ => false,
+        };
+        if changes_history && self.versus.is_some() {
+            self.message = Some("Not while a match is being played".to_string());
+            return;
+        }
+
         if keycode == event::KeyCode::Escape {
-            event::quit(ctx);
+            // Quit the way closing the window does, which saves the session
+            if !self.quit_event(ctx) {
+                event::quit(ctx);
+            }
+        } else if ctrl && keycode == event::KeyCode::S {
+            self.save_pgn();
+        } else if ctrl && keycode == event::KeyCode::E {
+            self.export_svg();
+        } else if ctrl && keycode == event::KeyCode::G {
+            self.export_gif();
+        } else if ctrl && keycode == event::KeyCode::K {
+            self.choose_engine();
+        } else if ctrl && keycode == event::KeyCode::L {
+            self.next_level();
+        } else if ctrl && keycode == event::KeyCode::B {
+            self.swap_sides();
+        } else if ctrl && keycode == event::KeyCode::R {
+            self.start_review();
+        } else if ctrl && keycode == event::KeyCode::O {
+            self.open_pgn();
+        } else if ctrl && keymods.contains(event::KeyMods::SHIFT) && keycode == event::KeyCode::C {
+            self.copy(pgn::write(&self.history, pgn::result(&self.history)), "game as PGN");
+        } else if ctrl && keymods.contains(event::KeyMods::SHIFT) && keycode == event::KeyCode::T {
+            self.copy(text::board(&self.game.board, true, self.flipped), "position as Unicode text");
+        } else if ctrl && keycode == event::KeyCode::T {
+            self.copy(text::board(&self.game.board, false, self.flipped), "position as text");
+        } else if ctrl && keycode == event::KeyCode::C {
+            self.copy(self.game.get_fen(), "position as FEN");
+        } else if ctrl && keycode == event::KeyCode::V {
+            self.paste();
+        } else if ctrl && keycode == event::KeyCode::F {
+            self.fen_input = Some(String::new());
+        } else if ctrl && keycode == event::KeyCode::Z {
+            self.undo();
+        } else if ctrl && keycode == event::KeyCode::Y {
+            self.redo();
+        } else if keycode == event::KeyCode::Left {
+            self.step_back(1);
+        } else if keycode == event::KeyCode::Right {
+            self.step_forward(1);
+        } else if keycode == event::KeyCode::Up {
+            self.step_back(2);
+        } else if keycode == event::KeyCode::Down {
+            self.step_forward(2);
+        } else if keycode == event::KeyCode::Home {
+            self.goto(Vec::new());
+        } else if keycode == event::KeyCode::End {
+            self.goto(self.history.line_end(&self.cursor));
         } else if keycode == event::KeyCode::R {
             self.game = Game::new();
-            self.history = Vec::new();
+            self.history = MoveTree::new(self.game.get_fen());
             self.viewing_history = false;
+            self.cursor = Vec::new();
+            self.redo_stack = Vec::new();
+            self.history_scroll = 0;
+            self.review = None;
             self.selected_square = None;
             self.highlighted_squares = Vec::new();
+            self.save_session();
+        } else if keycode == event::KeyCode::A {
+            self.toggle_analysis();
+        } else if keycode == event::KeyCode::Equals || keycode == event::KeyCode::NumpadAdd {
+            self.change_multipv(1);
+        } else if keycode == event::KeyCode::Minus || keycode == event::KeyCode::NumpadSubtract {
+            self.change_multipv(-1);
+        } else if keycode == event::KeyCode::H {
+            self.ask_hint();
+        } else if keycode == event::KeyCode::F {
+            self.flipped = !self.flipped;
+        } else if keycode == event::KeyCode::C {
+            self.coordinates = !self.coordinates;
+        } else if keycode == event::KeyCode::M {
+            self.last_move_shown = !self.last_move_shown;
+        } else if keycode == event::KeyCode::P {
+            let path = self.history.promote(&self.cursor);
+            self.redo_stack = Vec::new();
+            self.goto(path);
+            self.save_session();
+        } else if keycode == event::KeyCode::Delete {
+            if let Some(path) = self.history.delete_variation(&self.cursor) {
+                self.redo_stack = Vec::new();
+                self.goto(path);
+                self.history_scroll = self.history_scroll.min(self.max_history_scroll());
+                self.save_session();
+            }
+        }
+    }
+
+    /// Save the session when the window is closed
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        self.save_session();
+        if let Some(versus) = self.versus.take() {
+            self.end_match(&versus, "Match stopped");
+        }
+        false
+    }
+
+    /// Type into the FEN entry box
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        if let Some(input) = &mut self.fen_input {
+            if !character.is_control() {
+                input.push(character);
+            }
+        }
+    }
+}
+
+/// Options given on the command line.
+#[derive(Default)]
+struct Options {
+    pgn: Option<String>, // PGN file to load
+    fen: Option<String>, // Position to start the game from
+    gif: Option<String>, // Animated GIF to export the game to instead of opening the window
+    delay: Option<u32>, // Milliseconds per move in the exported GIF
+    no_captions: bool, // Leave out the moves below the board in the exported GIF
+    print: bool, // Print the final position as text instead of opening the window
+    unicode: bool, // Print chess glyphs instead of letters
+    engine: Option<String>, // UCI engine binary to play against
+    level: Option<ai::Level>, // Strength of the built-in computer to play against
+    multipv: Option<usize>, // Number of engine lines shown in the analysis
+    computer_colour: Option<Colour>,
+    versus: Option<[versus::Player; 2]>, // Players of a match to play instead of a game against the user
+    games: Option<usize>, // Number of games in the match
+    match_pgn: Option<String>, // PGN file the games of the match and its score table are written to
+    book: Option<String>, // Polyglot opening book
+    syzygy: Option<String>, // Directory of Syzygy endgame tables
+}
+
+/// Usage shown when the command line can't be read.
+const USAGE: &str = "Usage: chess-gui [options] [game.pgn]
+       chess-gui perft <depth> [FEN]
+Options:
+  --fen <FEN>                  Start from the given position
+  --gif <file.gif>             Export the game as an animated GIF instead of opening the window
+  --delay <ms>                 Milliseconds per move in the exported GIF
+  --no-captions                Leave out the moves below the board in the exported GIF
+  --print                      Print the final position as text instead of opening the window
+  --unicode                    Print chess glyphs instead of letters
+  --engine <path>              Play against a UCI engine
+  --level <level>              Play against the built-in computer: beginner, easy, medium or hard
+  --multipv <lines>            Number of engine lines shown in the analysis, 1 to 5
+  --computer-side <white|black>
+                               Side the engine or computer plays, black by default
+                               (--engine-side is accepted as well)
+  --book <book.bin>            Let the computer play from a Polyglot opening book and show its moves
+  --syzygy <dir>               Probe the Syzygy endgame tables in the directory
+  --match <player> <player>    Let two players play a match, each a UCI engine path or a built-in level
+  --games <n>                  Number of games in the match, 2 by default, colours alternating
+  --match-pgn <file.pgn>       File the match games and score table are written to, match.pgn by default";
+
+/// Reads the command line, see USAGE.
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fen" => options.fen = Some(args.next().ok_or("--fen needs a FEN string")?),
+            "--gif" => options.gif = Some(args.next().ok_or("--gif needs a file name")?),
+            "--delay" => {
+                let delay = args.next().ok_or("--delay needs a number of milliseconds")?;
+                options.delay = Some(delay.parse().map_err(|_| format!("Invalid delay {}", delay))?);
+            }
+            "--no-captions" => options.no_captions = true,
+            "--print" => options.print = true,
+            "--unicode" => options.unicode = true,
+            "--engine" => options.engine = Some(args.next().ok_or("--engine needs the path of a UCI engine")?),
+            "--level" => {
+                let name = args.next().unwrap_or_default();
+                options.level = Some(
+                    *ai::LEVELS
+                        .iter()
+                        .find(|level| level.name.eq_ignore_ascii_case(&name))
+                        .ok_or("--level needs beginner, easy, medium or hard")?,
+                );
+            }
+            "--multipv" => {
+                let lines = args.next().unwrap_or_default();
+                options.multipv = Some(
+                    lines
+                        .parse()
+                        .ok()
+                        .filter(|lines| (1..=MAX_MULTIPV).contains(lines))
+                        .ok_or("--multipv needs a number of lines from 1 to 5")?,
+                );
+            }
+            // --engine-side is the name from before the built-in computer could play
+            "--computer-side" | "--engine-side" => {
+                options.computer_colour = match args.next().as_deref() {
+                    Some("white") => Some(Colour::White),
+                    Some("black") => Some(Colour::Black),
+                    _ => return Err(format!("{} needs white or black", arg)),
+                }
+            }
+            "--book" => options.book = Some(args.next().ok_or("--book needs the path of a Polyglot book")?),
+            "--syzygy" => options.syzygy = Some(args.next().ok_or("--syzygy needs a directory of Syzygy tables")?),
+            "--match" => {
+                let (first, second) = args.next().zip(args.next()).ok_or("--match needs two players")?;
+                options.versus = Some([versus::Player::parse(&first), versus::Player::parse(&second)]);
+            }
+            "--games" => {
+                let games = args.next().unwrap_or_default();
+                options.games = Some(
+                    games
+                        .parse()
+                        .ok()
+                        .filter(|games| *games > 0)
+                        .ok_or(format!("Invalid number of games {}", games))?,
+                );
+            }
+            "--match-pgn" => options.match_pgn = Some(args.next().ok_or("--match-pgn needs a file name")?),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => options.pgn = Some(arg),
         }
     }
+    Ok(options)
 }
 
 pub fn main() -> GameResult {
-    let resource_dir = path::PathBuf::from("./resources");
+    // The perft subcommand counts positions to check move generation, see perft::command
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("perft") {
+        if let Err(e) = perft::command(&args[1..]) {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+        return Ok(());
+    }
+
+    let options = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        process::exit(2);
+    });
+
+    // Printing the position and exporting a GIF need no window
+    if options.print {
+        match read_game(&options) {
+            Ok(history) => {
+                let game = game_from_fen(history.fen(&history.line_end(&[])));
+                print!("{}", text::board(&game.board, options.unicode, false));
+            }
+            Err(e) => {
+                eprintln!("Failed to read game: {}", e);
+                process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+    if let Some(gif) = &options.gif {
+        let default = animation::Animation::default();
+        let animation = animation::Animation {
+            delay: options.delay.unwrap_or(default.delay),
+            captions: !options.no_captions,
+            ..default
+        };
+        let exported = read_game(&options).and_then(|history| animation.write(&history, path::Path::new(gif)));
+        if let Err(e) = exported {
+            eprintln!("Failed to export animation: {}", e);
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let resource_dir = path::PathBuf::from(RESOURCE_DIR);
 
     let context_builder = ContextBuilder::new("chess", "emil")
         .add_resource_path(resource_dir) // Import image files to GGEZ
@@ -273,13 +1521,72 @@ pub fn main() -> GameResult {
         .modules(conf::ModuleConf::default().audio(false));
     let (mut contex, event_loop) = context_builder.build().expect("Failed to build context.");
 
-    let state = AppState::new(&mut contex).expect("Failed to create state.");
+    let mut state = AppState::new(&mut contex).expect("Failed to create state.");
+    if options.fen.is_none() && options.pgn.is_none() && options.versus.is_none() {
+        state.offer_resume();
+    }
+    if let Some(fen) = &options.fen {
+        state.load_fen(fen);
+    }
+    if let Some(pgn) = &options.pgn {
+        state.load_pgn(path::Path::new(pgn));
+    }
+    if let Some(colour) = options.computer_colour {
+        state.computer_colour = colour;
+    }
+    if let Some(multipv) = options.multipv {
+        state.multipv = multipv;
+    }
+    if let Some(level) = options.level {
+        state.level = Some(level);
+        state.announce_computer();
+    }
+    if let Some(engine) = &options.engine {
+        state.start_engine(path::Path::new(engine));
+    }
+    if let Some(book) = &options.book {
+        match book::Book::load(path::Path::new(book)) {
+            Ok(book) => state.book = Some(book),
+            Err(e) => state.message = Some(format!("Failed to load opening book: {}", e)),
+        }
+    }
+    if let Some(syzygy) = &options.syzygy {
+        match tablebase::Tablebase::open(path::Path::new(syzygy)) {
+            Ok(tablebase) => state.tablebase = Some(tablebase),
+            Err(e) => state.message = Some(format!("Failed to open endgame tables: {}", e)),
+        }
+    }
+    if let Some(players) = options.versus {
+        let pgn = options.match_pgn.unwrap_or_else(|| "match.pgn".to_string());
+        state.start_match(players, options.games.unwrap_or(2), path::PathBuf::from(pgn));
+    }
     event::run(contex, event_loop, state) // Run window event loop
 }
 
+/// Reads the game given on the command line without opening the window.
+/// The PGN file is read if there is one, otherwise the game starts from the given FEN string or the start position.
+fn read_game(options: &Options) -> Result<MoveTree, String> {
+    if let Some(file) = &options.pgn {
+        let text = fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+        return pgn::read(&text).map_err(|e| format!("{}: {}", file, e));
+    }
+    match &options.fen {
+        Some(fen) => {
+            fen::validate(fen.trim()).map_err(|e| format!("Invalid FEN: {}", e))?;
+            Ok(MoveTree::new(game_from_fen(fen.trim()).get_fen()))
+        }
+        None => Ok(MoveTree::new(Game::new().get_fen())),
+    }
+}
+
 fn move_to_string(from: (usize, usize), to: (usize, usize)) -> String {
+    format!("{} {}", square_to_string(from), square_to_string(to))
+}
+
+/// Converts a (rank, file) tuple to a square name such as "e4".
+fn square_to_string(square: (usize, usize)) -> String {
     let mut string = String::new();
-    match from.1 {
+    match square.1 {
         0 => string.push('a'),
         1 => string.push('b'),
         2 => string.push('c'),
@@ -290,27 +1597,66 @@ fn move_to_string(from: (usize, usize), to: (usize, usize)) -> String {
         7 => string.push('h'),
         _ => panic!("No such file"),
     };
-    match from.0 {
-        0..=7 => string.push(char::from_digit(8 - from.0 as u32, 10).unwrap()),
+    match square.0 {
+        0..=7 => string.push(char::from_digit(8 - square.0 as u32, 10).unwrap()),
         _ => panic!("No such rank"),
     };
-    string.push(' ');
-    match to.1 {
-        0 => string.push('a'),
-        1 => string.push('b'),
-        2 => string.push('c'),
-        3 => string.push('d'),
-        4 => string.push('e'),
-        5 => string.push('f'),
-        6 => string.push('g'),
-        7 => string.push('h'),
-        _ => panic!("No such file"),
+    string
+}
+
+/// Converts a square name such as "e4" to a (rank, file) tuple.
+fn string_to_square(string: &str) -> Option<(usize, usize)> {
+    let mut chars = string.chars();
+    let file = match chars.next()? {
+        c @ 'a'..='h' => c as usize - 'a' as usize,
+        _ => return None,
     };
-    match to.0 {
-        0..=7 => string.push(char::from_digit(8 - to.0 as u32, 10).unwrap()),
-        _ => panic!("No such rank"),
+    let rank = match chars.next()? {
+        c @ '1'..='8' => 8 - c.to_digit(10)? as usize,
+        _ => return None,
     };
-    string
+    match chars.next() {
+        None => Some((rank, file)),
+        Some(_) => None,
+    }
+}
+
+/// Converts between a (rank, file) tuple and the (row, column) it is drawn at, which is the same unless the board is flipped.
+fn flip_square((rank, file): (usize, usize), flipped: bool) -> (usize, usize) {
+    if flipped {
+        (7 - rank, 7 - file)
+    } else {
+        (rank, file)
+    }
+}
+
+/// Converts a promotion piece to the character passed to Game::select_promotion.
+fn promotion_char(piece: Piece) -> char {
+    match piece {
+        Piece::Rook(_) => 'r',
+        Piece::Bishop(_) => 'b',
+        Piece::Knight(_) => 'n',
+        _ => 'q',
+    }
+}
+
+/// A book move for the computer to play at once instead of searching, None when out of book.
+/// Matches never play from the book, their moves come from the contestants through Match::think.
+fn book_move(book: Option<&book::Book>, versus: Option<&versus::Match>, game: &Game) -> Option<san::SanMove> {
+    if versus.is_some() {
+        return None;
+    }
+    book?.pick(game)
+}
+
+/// FEN string of the standard start position.
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// Creates a new game with the state given by a FEN string.
+fn game_from_fen(fen: &str) -> Game {
+    let mut game = Game::new();
+    game.set_state_from_fen(fen);
+    game
 }
 
 fn get_colour(piece: Piece) -> Option<Colour> {
@@ -323,4 +1669,75 @@ fn get_colour(piece: Piece) -> Option<Colour> {
         | Piece::Pawn(c) => Some(c),
         Piece::Empty => None,
     }
-}
\ No newline at end of file
+}
+
+/// Returns the colour of the other player.
+fn opponent(colour: Colour) -> Colour {
+    match colour {
+        Colour::White => Colour::Black,
+        Colour::Black => Colour::White,
+    }
+}
+
+/// All moves the player to move can make, as (from, to) pairs of (rank, file) tuples.
+fn legal_moves(game: &Game) -> Vec<((usize, usize), (usize, usize))> {
+    let mut moves = Vec::new();
+    for rank in 0..8 {
+        for file in 0..8 {
+            if get_colour(game.board[rank][file]) == Some(game.current_turn) {
+                for to in game.board[rank][file].get_valid_moves(
+                    (rank, file),
+                    &game.board,
+                    game.en_passant_square,
+                    game.castlings,
+                    game.current_turn,
+                ) {
+                    moves.push(((rank, file), to));
+                }
+            }
+        }
+    }
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a Polyglot book holding 1. e4 for the start position and loads it.
+    fn e4_book(path: &path::Path) -> book::Book {
+        let mut entry = 0x463b96181691fc9c_u64.to_be_bytes().to_vec();
+        entry.extend(((1 << 9) | (4 << 6) | (3 << 3) | 4_u16).to_be_bytes());
+        entry.extend(1_u16.to_be_bytes());
+        entry.extend(0_u32.to_be_bytes());
+        fs::write(path, entry).unwrap();
+        let book = book::Book::load(path).unwrap();
+        fs::remove_file(path).unwrap();
+        book
+    }
+
+    #[test]
+    fn matches_are_played_by_the_contestants() {
+        let path = std::env::temp_dir().join(format!("chess-book-{}.bin", std::process::id()));
+        let book = e4_book(&path);
+        let history = MoveTree::new(START_FEN.to_string());
+        let game = game_from_fen(START_FEN);
+        assert_eq!(book_move(Some(&book), None, &game), Some(((6, 4), (4, 4), None)));
+
+        let players = [versus::Player::Computer(ai::LEVELS[3]), versus::Player::Computer(ai::LEVELS[3])];
+        let mut versus = versus::Match::start(players, 2, path).unwrap();
+        assert_eq!(book_move(Some(&book), Some(&versus), &game), None);
+
+        // The move is asked of the player with white instead
+        let deadline = std::time::Instant::now() + Duration::from_secs(30);
+        let found = loop {
+            if let Some(found) = versus.think(&history) {
+                break found;
+            }
+            assert!(std::time::Instant::now() < deadline, "the contestant found no move");
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        let (from, to, _promotion) = found.unwrap();
+        assert!(legal_moves(&game).contains(&(from, to)));
+    }
+}
@@ -0,0 +1,141 @@
+use super::*;
+use san::SanMove;
+
+/// ## perft
+/// Counts the positions reached after playing every sequence of legal moves of the given depth.
+/// Moves come from Piece::get_valid_moves and are played with Game::take_turn, so wrong counts point at the chess backend.
+/// Promotions count once for every piece a pawn can promote to.
+/// The game is cloned for every move instead of going through FEN strings.
+pub fn perft(game: &Game, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = moves(game);
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    moves.into_iter().map(|m| perft(&play(game, m), depth - 1)).sum()
+}
+
+/// ## divide
+/// The perft count below each legal move of the position, with the moves in UCI notation.
+/// Comparing these with another move generator shows which move a wrong count comes from.
+/// At depth 0 no moves are played, so there is nothing to divide.
+pub fn divide(fen: &str, depth: usize) -> Vec<(String, u64)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+    let game = game_from_fen(fen);
+    moves(&game)
+        .into_iter()
+        .map(|m| {
+            let (from, to, promotion) = m;
+            let mut uci = square_to_string(from) + &square_to_string(to);
+            uci.extend(promotion);
+            (uci, perft(&play(&game, m), depth - 1))
+        })
+        .collect()
+}
+
+/// ## command
+/// Runs the perft subcommand: `perft <depth> [FEN]`.
+/// Prints the count below each move and the total, in the format of the "go perft" command of UCI engines.
+pub fn command(args: &[String]) -> Result<(), String> {
+    let depth = args
+        .first()
+        .and_then(|depth| depth.parse().ok())
+        .ok_or("perft needs a depth")?;
+    let fen = match args.get(1..) {
+        Some(fen) if !fen.is_empty() => fen.join(" "),
+        _ => START_FEN.to_string(),
+    };
+    fen::validate(&fen).map_err(|e| format!("Invalid FEN: {}", e))?;
+
+    let divided = divide(&fen, depth);
+    for (uci, nodes) in &divided {
+        println!("{}: {}", uci, nodes);
+    }
+    // The position itself is the one node at depth 0
+    let total = if depth == 0 { 1 } else { divided.iter().map(|(_uci, nodes)| nodes).sum() };
+    println!();
+    println!("Nodes searched: {}", total);
+    Ok(())
+}
+
+/// The legal moves of the game, with a move for every promotion piece where a pawn promotes.
+fn moves(game: &Game) -> Vec<SanMove> {
+    let mut moves = Vec::new();
+    for (from, to) in legal_moves(game) {
+        if matches!(game.board[from.0][from.1], Piece::Pawn(_)) && (to.0 == 0 || to.0 == 7) {
+            moves.extend(['q', 'r', 'b', 'n'].iter().map(|promotion| (from, to, Some(*promotion))));
+        } else {
+            moves.push((from, to, None));
+        }
+    }
+    moves
+}
+
+/// The game after playing the move.
+fn play(game: &Game, (from, to, promotion): SanMove) -> Game {
+    let mut child = game.clone();
+    child.select_promotion(promotion.unwrap_or('q'));
+    child.take_turn(move_to_string(from, to));
+    child
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks the counts of a position against reference counts, starting at depth 1.
+    fn check(fen: &str, counts: &[u64]) {
+        for (depth, count) in counts.iter().enumerate() {
+            assert_eq!(perft(&game_from_fen(fen), depth + 1), *count, "perft {} of {}", depth + 1, fen);
+        }
+    }
+
+    #[test]
+    fn start_position() {
+        check(START_FEN, &[20, 400, 8902, 197281]);
+    }
+
+    #[test]
+    fn kiwipete() {
+        // Castling, en passant, pins and promotions all in one position
+        check("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &[48, 2039, 97862]);
+    }
+
+    #[test]
+    fn en_passant_pins() {
+        check("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812, 43238]);
+    }
+
+    #[test]
+    fn promotions_and_castling_rights() {
+        check("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6, 264, 9467]);
+    }
+
+    #[test]
+    fn promotion_with_check() {
+        check("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1486, 62379]);
+    }
+
+    #[test]
+    fn middlegame() {
+        check("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P3/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", &[46, 2079, 89890]);
+    }
+
+    #[test]
+    fn depth_zero() {
+        assert_eq!(perft(&game_from_fen(START_FEN), 0), 1);
+        assert!(divide(START_FEN, 0).is_empty());
+    }
+
+    #[test]
+    fn divide_adds_up() {
+        let divided = divide(START_FEN, 2);
+        assert_eq!(divided.len(), 20);
+        assert!(divided.contains(&("e2e4".to_string(), 20)));
+        assert_eq!(divided.iter().map(|(_uci, nodes)| nodes).sum::<u64>(), 400);
+    }
+}
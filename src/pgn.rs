@@ -0,0 +1,520 @@
+use super::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Longest line of movetext written to a PGN file.
+const LINE_LENGTH: usize = 80;
+/// Plies without a capture or pawn move after which games are drawn by the fifty move rule.
+const FIFTY_MOVES: usize = 100;
+
+/// ## ParseError
+/// An error found while reading a PGN file.
+/// line and column start at 1 and point at the start of the offending token.
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// The parts of PGN that matter when replaying a game.
+enum Token {
+    Tag(String, String),
+    Move(String),
+    VariationStart,
+    VariationEnd,
+    Result,
+}
+
+/// ## read
+/// Reads the first game of a PGN file and replays it into a move tree, including variations.
+/// Every move is converted to the "e2 e4" form and checked by Game::take_turn.
+pub fn read(text: &str) -> Result<MoveTree, ParseError> {
+    let tokens = tokenize(text)?;
+
+    // Only the tags of the first game count, they end at its first move or result
+    let mut start_fen = Game::new().get_fen();
+    for (token, line, column) in &tokens {
+        match token {
+            Token::Tag(name, value) if name == "FEN" => {
+                fen::validate(value).map_err(|e| ParseError {
+                    line: *line,
+                    column: *column,
+                    message: format!("Invalid FEN tag: {}", e),
+                })?;
+                start_fen = value.clone();
+            }
+            Token::Tag(..) => (),
+            _ => break,
+        }
+    }
+    let mut history = MoveTree::new(start_fen);
+
+    // The path to the last move played, and where to continue when each open variation ends
+    let mut path: Path = Vec::new();
+    let mut variations: Vec<Path> = Vec::new();
+    for (token, line, column) in tokens {
+        let error = |message: String| ParseError {
+            line,
+            column,
+            message,
+        };
+        match token {
+            Token::Tag(..) => (),
+            Token::Move(san) => {
+                let game = game_from_fen(history.fen(&path));
+                let (from, to, promotion) = san::san_to_move(&game, &san).map_err(error)?;
+                path = history.play(&path, from, to, promotion);
+            }
+            Token::VariationStart => {
+                // A variation replaces the move before it
+                variations.push(path.clone());
+                if path.pop().is_none() {
+                    return Err(error("Variation before the first move".to_string()));
+                }
+            }
+            Token::VariationEnd => {
+                path = variations
+                    .pop()
+                    .ok_or_else(|| error("Unmatched closing parenthesis".to_string()))?;
+            }
+            Token::Result => {
+                if !variations.is_empty() {
+                    return Err(error("Game ended inside a variation".to_string()));
+                }
+                break;
+            }
+        }
+    }
+    Ok(history)
+}
+
+/// Splits PGN text into tokens together with the line and column they start at.
+/// Comments, move numbers, annotation glyphs and escaped lines are skipped.
+fn tokenize(text: &str) -> Result<Vec<(Token, usize, usize)>, ParseError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let (mut i, mut line, mut column) = (0, 1, 1);
+
+    // Moves the position forward one character, keeping track of lines and columns
+    let advance = |i: &mut usize, line: &mut usize, column: &mut usize| {
+        if chars[*i] == '\n' {
+            *line += 1;
+            *column = 1;
+        } else {
+            *column += 1;
+        }
+        *i += 1;
+    };
+
+    while i < chars.len() {
+        let (start_line, start_column) = (line, column);
+        let error = |message: &str| ParseError {
+            line: start_line,
+            column: start_column,
+            message: message.to_string(),
+        };
+        match chars[i] {
+            c if c.is_whitespace() => advance(&mut i, &mut line, &mut column),
+            '%' if column == 1 => {
+                while i < chars.len() && chars[i] != '\n' {
+                    advance(&mut i, &mut line, &mut column);
+                }
+            }
+            ';' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    advance(&mut i, &mut line, &mut column);
+                }
+            }
+            '{' => {
+                while i < chars.len() && chars[i] != '}' {
+                    advance(&mut i, &mut line, &mut column);
+                }
+                if i == chars.len() {
+                    return Err(error("Unterminated comment"));
+                }
+                advance(&mut i, &mut line, &mut column);
+            }
+            '[' => {
+                let end = chars[i..]
+                    .iter()
+                    .position(|c| *c == ']' || *c == '\n')
+                    .map(|offset| i + offset)
+                    .filter(|end| chars[*end] == ']')
+                    .ok_or_else(|| error("Unterminated tag"))?;
+                let tag: String = chars[i + 1..end].iter().collect();
+                let (name, value) = tag
+                    .trim()
+                    .split_once(' ')
+                    .ok_or_else(|| error("Tag without a value"))?;
+                let value = value.trim();
+                if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+                    return Err(error("Tag value must be quoted"));
+                }
+                let value = value[1..value.len() - 1].replace("\\\"", "\"").replace("\\\\", "\\");
+                tokens.push((Token::Tag(name.to_string(), value), start_line, start_column));
+                while i <= end {
+                    advance(&mut i, &mut line, &mut column);
+                }
+            }
+            '(' => {
+                tokens.push((Token::VariationStart, start_line, start_column));
+                advance(&mut i, &mut line, &mut column);
+            }
+            ')' => {
+                tokens.push((Token::VariationEnd, start_line, start_column));
+                advance(&mut i, &mut line, &mut column);
+            }
+            '*' => {
+                tokens.push((Token::Result, start_line, start_column));
+                advance(&mut i, &mut line, &mut column);
+            }
+            '$' => {
+                advance(&mut i, &mut line, &mut column);
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    advance(&mut i, &mut line, &mut column);
+                }
+            }
+            c if c.is_ascii_alphanumeric() => {
+                let mut symbol = String::new();
+                while i < chars.len()
+                    && (chars[i].is_ascii_alphanumeric() || "+#=:-/_!?".contains(chars[i]))
+                {
+                    symbol.push(chars[i]);
+                    advance(&mut i, &mut line, &mut column);
+                }
+                if symbol.chars().all(|c| c.is_ascii_digit()) {
+                    // Move numbers are followed by one or more periods
+                    while i < chars.len() && chars[i] == '.' {
+                        advance(&mut i, &mut line, &mut column);
+                    }
+                } else if symbol == "1-0" || symbol == "0-1" || symbol == "1/2-1/2" {
+                    tokens.push((Token::Result, start_line, start_column));
+                } else {
+                    tokens.push((Token::Move(symbol), start_line, start_column));
+                }
+            }
+            '!' | '?' => advance(&mut i, &mut line, &mut column),
+            c => return Err(error(&format!("Unexpected character '{}'", c))),
+        }
+    }
+    Ok(tokens)
+}
+
+/// ## write
+/// Writes the main line of the history as a PGN game with the Seven Tag Roster.
+/// result is the game termination marker, "1-0", "0-1", "1/2-1/2" or "*".
+pub fn write(history: &MoveTree, result: &str) -> String {
+    write_game(history, result, &[], false)
+}
+
+/// ## write_with_variations
+/// Writes the whole history as a PGN game, variations included.
+/// The extra tags replace tags of the Seven Tag Roster with the same name and are added after it otherwise.
+pub fn write_with_variations(history: &MoveTree, result: &str, extra_tags: &[(&str, String)]) -> String {
+    write_game(history, result, extra_tags, true)
+}
+
+fn write_game(history: &MoveTree, result: &str, extra_tags: &[(&str, String)], variations: bool) -> String {
+    let date = today();
+    let mut tags = vec![
+        ("Event", "Casual game"),
+        ("Site", "?"),
+        ("Date", date.as_str()),
+        ("Round", "-"),
+        ("White", "?"),
+        ("Black", "?"),
+        ("Result", result),
+    ];
+    // Games starting elsewhere than the standard start position get SetUp and FEN tags
+    if history.start_fen != START_FEN {
+        tags.push(("SetUp", "1"));
+        tags.push(("FEN", history.start_fen.as_str()));
+    }
+    if let Some(opening) = eco::opening(history, &history.line_end(&[])) {
+        tags.push(("ECO", opening.eco.as_str()));
+        tags.push(("Opening", opening.name.as_str()));
+    }
+    for (name, value) in extra_tags {
+        match tags.iter_mut().find(|(tag, _value)| tag == name) {
+            Some(tag) => tag.1 = value.as_str(),
+            None => tags.push((name, value.as_str())),
+        }
+    }
+
+    let mut pgn = String::new();
+    for (name, value) in tags {
+        pgn.push_str(&format!("[{} \"{}\"]\n", name, escape(value)));
+    }
+    pgn.push('\n');
+
+    let mut tokens = Vec::new();
+    if !history.children.is_empty() {
+        push_line(history, &mut tokens, vec![0], variations);
+    }
+    tokens.push(result.to_string());
+
+    // Parentheses are written next to the moves they enclose
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_LENGTH {
+            pgn.push_str(&line);
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() && !line.ends_with('(') && token != ")" {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    pgn.push_str(&line);
+    pgn.push('\n');
+    pgn
+}
+
+/// Pushes the movetext of the line starting with the move at the end of the path.
+/// Black moves only get a move number when they start a line or follow a variation.
+fn push_line(history: &MoveTree, tokens: &mut Vec<String>, mut path: Path, variations: bool) {
+    let mut number_black = true;
+    while let Some(node) = history.node(&path) {
+        match node.record.colour() {
+            Colour::White => tokens.push(format!("{}.", node.record.number)),
+            Colour::Black if number_black => tokens.push(format!("{}...", node.record.number)),
+            Colour::Black => (),
+        }
+        tokens.push(node.record.san.clone());
+        number_black = false;
+
+        // Variations are written right after the main continuation they are an alternative to
+        let (last, parent) = path.split_last().expect("Lines never start at the root");
+        let siblings = history.children(parent).map_or(0, |children| children.len());
+        if variations && *last == 0 && siblings > 1 {
+            for i in 1..siblings {
+                let mut variation = parent.to_vec();
+                variation.push(i);
+                tokens.push("(".to_string());
+                push_line(history, tokens, variation, variations);
+                tokens.push(")".to_string());
+            }
+            number_black = true;
+        }
+
+        path.push(0);
+    }
+}
+
+/// ## result
+/// The result of the main line as a PGN game termination marker.
+/// Besides checkmate and stalemate, games are drawn when a position occurs for the third time
+/// or by the fifty move rule. Every other game is still in progress.
+pub fn result(history: &MoveTree) -> &'static str {
+    let main_line = history.main_line();
+    let fen = match main_line.last() {
+        Some(node) if node.record.checkmate => {
+            return match node.record.colour() {
+                Colour::White => "1-0",
+                Colour::Black => "0-1",
+            }
+        }
+        last => last.map_or(history.start_fen.as_str(), |node| node.fen.as_str()),
+    };
+    if legal_moves(&game_from_fen(fen)).is_empty() {
+        return "1/2-1/2";
+    }
+
+    // Positions are compared by placement, side to move, castling rights and en passant square
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    for fen in std::iter::once(history.start_fen.as_str()).chain(main_line.iter().map(|node| node.fen.as_str())) {
+        let position: Vec<&str> = fen.split_whitespace().take(4).collect();
+        *occurrences.entry(position.join(" ")).or_insert(0) += 1;
+    }
+    // The halfmove clock of the FEN string counts the plies since the last capture or pawn move
+    let clock = fen.split_whitespace().nth(4).and_then(|clock| clock.parse::<usize>().ok()).unwrap_or(0);
+    if occurrences.values().any(|count| *count >= 3) || clock >= FIFTY_MOVES {
+        "1/2-1/2"
+    } else {
+        "*"
+    }
+}
+
+/// ## read_tags
+/// Reads the tags of the first game of a PGN file, in order.
+pub fn read_tags(text: &str) -> Result<Vec<(String, String)>, ParseError> {
+    Ok(tokenize(text)?
+        .into_iter()
+        .filter_map(|(token, _line, _column)| match token {
+            Token::Tag(name, value) => Some((name, value)),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Escapes quotes and backslashes in a tag value.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Today's date in the PGN date format, "YYYY.MM.DD".
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() / 86400) as i64;
+    date(days)
+}
+
+/// The date a number of days after 1970-01-01 in the PGN date format, in the proleptic Gregorian calendar.
+fn date(days: i64) -> String {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The movetext of a written game, without its tags.
+    fn movetext(pgn: &str) -> &str {
+        pgn.split("\n\n").nth(1).unwrap_or_default().trim()
+    }
+
+    #[test]
+    fn round_trip_with_variations() {
+        let text = "1. e4 e5 (1... c5 2. Nf3) 2. Nf3 Nc6 (2... d6) 3. Bb5 *";
+        let history = read(text).unwrap();
+        let written = write_with_variations(&history, "*", &[]);
+        assert_eq!(movetext(&written), text);
+        let reread = read(&written).unwrap();
+        assert_eq!(write_with_variations(&reread, "*", &[]), written);
+    }
+
+    #[test]
+    fn main_line_only() {
+        let history = read("1. e4 e5 (1... c5 2. Nf3) 2. Nf3 *").unwrap();
+        assert_eq!(movetext(&write(&history, "*")), "1. e4 e5 2. Nf3 *");
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        let text = "1. e4 {Best by test} e5 ; The classical reply\n2. Nf3 $1 Nc6 *";
+        let history = read(text).unwrap();
+        let written = write(&history, "*");
+        assert_eq!(movetext(&written), "1. e4 e5 2. Nf3 Nc6 *");
+        assert_eq!(movetext(&write(&read(&written).unwrap(), "*")), "1. e4 e5 2. Nf3 Nc6 *");
+    }
+
+    #[test]
+    fn start_position_tags() {
+        let fen = "8/8/4k3/8/8/4K3/4P3/8 w - - 0 1";
+        let text = format!("[SetUp \"1\"]\n[FEN \"{}\"]\n\n1. Kd3 Kd6 2. e4 *", fen);
+        let written = write(&read(&text).unwrap(), "*");
+        assert!(written.contains("[SetUp \"1\"]\n"));
+        assert!(written.contains(&format!("[FEN \"{}\"]\n", fen)));
+        assert_eq!(movetext(&written), "1. Kd3 Kd6 2. e4 *");
+        assert_eq!(read(&written).unwrap().start_fen, fen);
+    }
+
+    #[test]
+    fn standard_start_has_no_setup() {
+        let written = write(&read("1. d4 *").unwrap(), "*");
+        assert!(!written.contains("SetUp"));
+        assert!(written.contains("[Result \"*\"]\n"));
+    }
+
+    #[test]
+    fn extra_tags_replace_the_roster() {
+        let history = read("1. d4 *").unwrap();
+        let written = write_with_variations(&history, "*", &[("White", "Alice".to_string()), ("Cursor", "1".to_string())]);
+        assert!(written.contains("[White \"Alice\"]\n"));
+        assert!(!written.contains("[White \"?\"]"));
+        assert!(written.contains("[Cursor \"1\"]\n"));
+    }
+
+    /// The tokens of PGN text written out, to compare them easily.
+    fn tokens(text: &str) -> Vec<String> {
+        tokenize(text)
+            .unwrap()
+            .into_iter()
+            .map(|(token, line, column)| match token {
+                Token::Tag(name, value) => format!("{}={} {}:{}", name, value, line, column),
+                Token::Move(san) => format!("{} {}:{}", san, line, column),
+                Token::VariationStart => format!("( {}:{}", line, column),
+                Token::VariationEnd => format!(") {}:{}", line, column),
+                Token::Result => format!("result {}:{}", line, column),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tokenizer() {
+        let text = "[Event \"A \\\"quoted\\\" game\"]\n% escaped line\n1. e4 {comment} e5 $2 (1... c5!?)\n2. Nf3 ; rest of line\n1-0";
+        assert_eq!(
+            tokens(text),
+            vec![
+                "Event=A \"quoted\" game 1:1",
+                "e4 3:4",
+                "e5 3:17",
+                "( 3:23",
+                "c5!? 3:29",
+                ") 3:33",
+                "Nf3 4:4",
+                "result 5:1",
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenizer_errors() {
+        let error = tokenize("1. e4 {never closed").err().unwrap();
+        assert_eq!((error.line, error.column), (1, 7));
+        let error = tokenize("[Event \"x\"]\n[Site \"y\"\n").err().unwrap();
+        assert_eq!((error.line, error.column), (2, 1));
+        let error = tokenize("1. e4 & e5").err().unwrap();
+        assert_eq!((error.line, error.column), (1, 7));
+    }
+
+    #[test]
+    fn illegal_move_position() {
+        let error = read("[Event \"x\"]\n\n1. e4 e5\n2. Ke3 *").err().unwrap();
+        assert_eq!((error.line, error.column), (4, 4));
+        assert_eq!(error.to_string(), "line 4, column 4: Illegal move Ke3");
+    }
+
+    #[test]
+    fn invalid_fen_tag() {
+        let error = read("[Event \"x\"]\n[FEN \"8/8/8 w - - 0 1\"]\n\n*").err().unwrap();
+        assert_eq!((error.line, error.column), (2, 1));
+        assert!(error.message.starts_with("Invalid FEN tag"));
+    }
+
+    #[test]
+    fn later_games_are_ignored() {
+        let text = "[Event \"a\"]\n\n1. e4 *\n\n[Event \"b\"]\n[FEN \"4k3/8/8/8/8/8/8/4K3 w - - 0 1\"]\n\n*";
+        let history = read(text).unwrap();
+        assert_eq!(history.start_fen, Game::new().get_fen());
+        assert_eq!(history.main_line().len(), 1);
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(date(0), "1970.01.01");
+        assert_eq!(date(10651), "1999.03.01");
+        assert_eq!(date(11016), "2000.02.29");
+        assert_eq!(date(20088), "2024.12.31");
+        assert_eq!(today().len(), 10);
+    }
+}
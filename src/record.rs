@@ -0,0 +1,51 @@
+use super::*;
+
+/// ## MoveRecord
+/// A move as it was passed to Game::take_turn, together with what it did on the board.
+/// Squares are (rank, file) tuples like the ones passed to move_to_string.
+#[derive(Clone)]
+pub struct MoveRecord {
+    pub piece: Piece,
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    pub promotion: Option<Piece>,
+    pub check: bool,
+    pub checkmate: bool,
+    pub number: usize, // Full move number the move was played on
+    pub san: String,
+}
+
+impl MoveRecord {
+    /// Records a move from the games before and after it was passed to Game::take_turn.
+    pub fn new(before: &Game, from: (usize, usize), to: (usize, usize), after: &Game) -> MoveRecord {
+        let piece = before.board[from.0][from.1];
+        let promotion = match piece {
+            Piece::Pawn(_) if after.board[to.0][to.1] != piece => Some(after.board[to.0][to.1]),
+            _ => None,
+        };
+
+        MoveRecord {
+            piece,
+            from,
+            to,
+            promotion,
+            check: san::in_check(&after.board, after.current_turn),
+            checkmate: matches!(after.game_state, murnion_chess::GameState::Checkmate),
+            number: fullmove_number(&before.get_fen()),
+            san: san::move_to_san(before, from, to, after),
+        }
+    }
+
+    /// The colour of the player who made the move.
+    pub fn colour(&self) -> Colour {
+        get_colour(self.piece).expect("A move is always made by a piece")
+    }
+}
+
+/// Reads the full move number from the last field of a FEN string.
+fn fullmove_number(fen: &str) -> usize {
+    fen.split_whitespace()
+        .nth(5)
+        .and_then(|number| number.parse().ok())
+        .unwrap_or(1)
+}
@@ -0,0 +1,261 @@
+use super::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+/// Time the engine gets to score each position of a reviewed game.
+const REVIEW_TIME: Duration = Duration::from_millis(300);
+/// Depth the built-in computer searches each position of a reviewed game to.
+const REVIEW_DEPTH: usize = 3;
+/// Centipawn score of a forced mate, well beyond anything decided by material.
+const MATE_SCORE: i32 = 10_000;
+
+/// ## Class
+/// How good a move was, judged by how much it lowered the mover's winning chances.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Class {
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Class {
+    /// The annotation symbol written after a move of this class.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Class::Best => "!",
+            Class::Good => "",
+            Class::Inaccuracy => "?!",
+            Class::Mistake => "?",
+            Class::Blunder => "??",
+        }
+    }
+}
+
+/// ## Review
+/// Engine scores for every position of the main line of a game, searched in the background one position at a time.
+/// Moves are classified and players given an accuracy from the scores found so far.
+pub struct Review {
+    fens: Vec<String>, // Positions of the main line, the start position first
+    moves: Vec<((usize, usize), (usize, usize))>, // Moves between the positions
+    scores: Vec<Option<(i32, Option<san::SanMove>)>>, // Centipawns from white and the best move of each position
+    results: mpsc::Receiver<(usize, i32, Option<san::SanMove>)>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Review {
+    /// Starts reviewing the main line of the history, with the UCI engine at the path if given
+    /// and with the built-in computer otherwise.
+    pub fn start(history: &MoveTree, engine_path: Option<path::PathBuf>) -> Review {
+        let main_line = history.main_line();
+        let mut fens = vec![history.start_fen.clone()];
+        fens.extend(main_line.iter().map(|node| node.fen.clone()));
+        let moves = main_line.iter().map(|node| (node.record.from, node.record.to)).collect();
+        // Engines are given the moves leading up to each position, so that they know about repetitions
+        let uci_moves: Vec<String> = main_line.iter().map(|node| uci::move_to_uci(&node.record)).collect();
+        let start_fen = history.start_fen.clone();
+
+        let (sender, results) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let cancel = Arc::clone(&cancelled);
+        let positions = fens.clone();
+        thread::spawn(move || {
+            let mut engine = match engine_path {
+                Some(path) => match uci::Engine::start(&path) {
+                    Ok(engine) => Some(engine),
+                    Err(_) => return,
+                },
+                None => None,
+            };
+            for (i, fen) in positions.iter().enumerate() {
+                let (score, best) = match score(fen, &start_fen, &uci_moves[..i], engine.as_mut(), &cancel) {
+                    Some(scored) => scored,
+                    None => return,
+                };
+                let white = match game_from_fen(fen).current_turn {
+                    Colour::White => score,
+                    Colour::Black => -score,
+                };
+                if sender.send((i, white, best)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Review {
+            scores: vec![None; fens.len()],
+            fens,
+            moves,
+            results,
+            cancelled,
+        }
+    }
+
+    /// Collects the scores found since the last update.
+    /// Fails if the review stopped before every position was scored.
+    pub fn update(&mut self) -> Result<(), String> {
+        loop {
+            match self.results.try_recv() {
+                Ok((i, score, best)) => self.scores[i] = Some((score, best)),
+                Err(mpsc::TryRecvError::Empty) => return Ok(()),
+                Err(mpsc::TryRecvError::Disconnected) if self.is_finished() => return Ok(()),
+                Err(mpsc::TryRecvError::Disconnected) => return Err("The engine stopped".to_string()),
+            }
+        }
+    }
+
+    /// Number of positions scored and to be scored in total.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.scores.iter().filter(|score| score.is_some()).count(), self.scores.len())
+    }
+
+    /// Checks whether every position has been scored.
+    pub fn is_finished(&self) -> bool {
+        self.scores.iter().all(|score| score.is_some())
+    }
+
+    /// The class of the move at the end of the path, if it is a reviewed move of the main line.
+    pub fn class(&self, history: &MoveTree, path: &[usize]) -> Option<Class> {
+        let ply = path.len().checked_sub(1)?;
+        if path.iter().any(|i| *i != 0) || self.fens.get(ply + 1).map(|fen| fen.as_str()) != Some(history.fen(path)) {
+            return None;
+        }
+        let loss = self.loss(ply)?;
+        let best = self.scores[ply].and_then(|(_score, best)| best);
+        let played_best = best.is_some_and(|(from, to, _promotion)| (from, to) == self.moves[ply]);
+        Some(classify(loss, played_best))
+    }
+
+    /// Accuracy of a player from 0 to 100 over the moves reviewed so far, None before any move is reviewed.
+    /// Every move scores 100 when it keeps the winning chances and less the more it loses of them.
+    pub fn accuracy(&self, colour: Colour) -> Option<f32> {
+        let accuracies: Vec<f32> = (0..self.moves.len())
+            .filter(|ply| game_from_fen(&self.fens[*ply]).current_turn == colour)
+            .filter_map(|ply| self.loss(ply))
+            .map(move_accuracy)
+            .collect();
+        if accuracies.is_empty() {
+            None
+        } else {
+            Some(accuracies.iter().sum::<f32>() / accuracies.len() as f32)
+        }
+    }
+
+    /// How many percentage points of winning chances the move played from the position lost for its player.
+    fn loss(&self, ply: usize) -> Option<f32> {
+        let (before, _best) = (*self.scores.get(ply)?)?;
+        let (after, _best) = (*self.scores.get(ply + 1)?)?;
+        let sign = match game_from_fen(&self.fens[ply]).current_turn {
+            Colour::White => 1,
+            Colour::Black => -1,
+        };
+        Some((win_chance(sign * before) - win_chance(sign * after)).max(0.0))
+    }
+}
+
+impl Drop for Review {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// The class of a move losing the given percentage points of winning chances.
+/// The engine's own choice is the best move whatever the scores say.
+fn classify(loss: f32, played_best: bool) -> Class {
+    match loss {
+        _ if played_best => Class::Best,
+        _ if loss >= 15.0 => Class::Blunder,
+        _ if loss >= 10.0 => Class::Mistake,
+        _ if loss >= 5.0 => Class::Inaccuracy,
+        _ => Class::Good,
+    }
+}
+
+/// Accuracy of a single move from 0 to 100, falling off exponentially with the winning chances it lost.
+fn move_accuracy(loss: f32) -> f32 {
+    (103.1668 * (-0.04354 * loss).exp() - 3.1669).clamp(0.0, 100.0)
+}
+
+/// Winning chances in percent for a centipawn score.
+fn win_chance(centipawns: i32) -> f32 {
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.00368208 * centipawns as f32).exp()) - 1.0)
+}
+
+/// Scores a position in centipawns from the side to move and finds its best move.
+/// The position is given by its FEN string, and to engines by the moves leading up to it from the start FEN.
+/// Finished games are scored directly. None if the review is cancelled or the engine fails.
+fn score(
+    fen: &str,
+    start_fen: &str,
+    moves: &[String],
+    engine: Option<&mut uci::Engine>,
+    cancelled: &AtomicBool,
+) -> Option<(i32, Option<san::SanMove>)> {
+    let game = game_from_fen(fen);
+    if legal_moves(&game).is_empty() {
+        let score = if san::in_check(&game.board, game.current_turn) { -MATE_SCORE } else { 0 };
+        return Some((score, None));
+    }
+
+    let engine = match engine {
+        Some(engine) => engine,
+        None => return ai::analyse(fen, REVIEW_DEPTH, cancelled).map(|(score, best)| (score, Some(best))),
+    };
+    engine.go(start_fen, moves, REVIEW_TIME);
+    let mut score = uci::Score::Centipawns(0);
+    while !cancelled.load(Ordering::Relaxed) {
+        for event in engine.poll() {
+            match event {
+                uci::Event::Info(info) if info.multipv == 1 => score = info.score,
+                uci::Event::Info(_info) => (),
+                uci::Event::BestMove(bestmove) => {
+                    let centipawns = match score {
+                        uci::Score::Centipawns(cp) => cp,
+                        uci::Score::Mate(moves) if moves > 0 => MATE_SCORE,
+                        uci::Score::Mate(_) => -MATE_SCORE,
+                    };
+                    return Some((centipawns, uci::uci_to_move(&bestmove)));
+                }
+                uci::Event::Crashed | uci::Event::TimedOut => return None,
+            }
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn winning_chances() {
+        assert_eq!(win_chance(0), 50.0);
+        assert!((win_chance(300) - 75.11).abs() < 0.01);
+        assert!((win_chance(300) + win_chance(-300) - 100.0).abs() < 0.001);
+        assert!(win_chance(MATE_SCORE) > 99.9);
+        assert!(win_chance(-MATE_SCORE) < 0.1);
+    }
+
+    #[test]
+    fn loss_thresholds() {
+        assert!(classify(0.0, false) == Class::Good);
+        assert!(classify(4.9, false) == Class::Good);
+        assert!(classify(5.0, false) == Class::Inaccuracy);
+        assert!(classify(9.9, false) == Class::Inaccuracy);
+        assert!(classify(10.0, false) == Class::Mistake);
+        assert!(classify(14.9, false) == Class::Mistake);
+        assert!(classify(15.0, false) == Class::Blunder);
+        assert!(classify(40.0, true) == Class::Best);
+    }
+
+    #[test]
+    fn accuracy_formula() {
+        assert!((move_accuracy(0.0) - 100.0).abs() < 0.001);
+        assert!((move_accuracy(10.0) - 63.58).abs() < 0.01);
+        assert!((move_accuracy(50.0) - 8.53).abs() < 0.01);
+        assert_eq!(move_accuracy(100.0), 0.0);
+    }
+}
@@ -0,0 +1,302 @@
+use super::*;
+
+/// A move as the from and to squares, given as (rank, file) tuples,
+/// and the promotion piece as the character passed to Game::select_promotion.
+pub type SanMove = ((usize, usize), (usize, usize), Option<char>);
+
+/// ## piece_letter
+/// Returns the letter used for a piece in Standard Algebraic Notation.
+/// Pawns and empty squares have no letter.
+pub fn piece_letter(piece: Piece) -> Option<char> {
+    match piece {
+        Piece::King(_) => Some('K'),
+        Piece::Queen(_) => Some('Q'),
+        Piece::Rook(_) => Some('R'),
+        Piece::Bishop(_) => Some('B'),
+        Piece::Knight(_) => Some('N'),
+        Piece::Pawn(_) | Piece::Empty => None,
+    }
+}
+
+/// ## move_to_san
+/// Converts a move to Standard Algebraic Notation, e.g. "Nf3", "exd5", "O-O" or "e8=Q+".
+/// before is the game before the move was passed to Game::take_turn and after is the game after it.
+/// from and to are given as (rank, file) tuples like the ones passed to move_to_string.
+pub fn move_to_san(before: &Game, from: (usize, usize), to: (usize, usize), after: &Game) -> String {
+    let piece = before.board[from.0][from.1];
+    let mut san = String::new();
+
+    match piece {
+        Piece::King(_) if (to.1 as i32 - from.1 as i32).abs() == 2 => {
+            san.push_str(if to.1 > from.1 { "O-O" } else { "O-O-O" });
+        }
+        Piece::Pawn(_) => {
+            // Pawns changing file always capture, even when the target square is empty (en passant)
+            if from.1 != to.1 {
+                san.push_str(&square_to_string(from)[..1]);
+                san.push('x');
+            }
+            san.push_str(&square_to_string(to));
+            if to.0 == 0 || to.0 == 7 {
+                if let Some(letter) = piece_letter(after.board[to.0][to.1]) {
+                    san.push('=');
+                    san.push(letter);
+                }
+            }
+        }
+        _ => {
+            if let Some(letter) = piece_letter(piece) {
+                san.push(letter);
+            }
+            san.push_str(&disambiguation(before, from, to));
+            if before.board[to.0][to.1] != Piece::Empty {
+                san.push('x');
+            }
+            san.push_str(&square_to_string(to));
+        }
+    }
+
+    match after.game_state {
+        murnion_chess::GameState::Checkmate => san.push('#'),
+        _ if in_check(&after.board, after.current_turn) => san.push('+'),
+        _ => (),
+    }
+    san
+}
+
+/// ## disambiguation
+/// Returns the file, rank or square needed to tell the moving piece apart from
+/// other pieces of the same kind that could also move to the target square.
+fn disambiguation(before: &Game, from: (usize, usize), to: (usize, usize)) -> String {
+    let piece = before.board[from.0][from.1];
+    let mut others = Vec::new();
+    for rank in 0..8 {
+        for file in 0..8 {
+            if (rank, file) != from
+                && before.board[rank][file] == piece
+                && piece
+                    .get_valid_moves(
+                        (rank, file),
+                        &before.board,
+                        before.en_passant_square,
+                        before.castlings,
+                        before.current_turn,
+                    )
+                    .contains(&to)
+            {
+                others.push((rank, file));
+            }
+        }
+    }
+
+    let square = square_to_string(from);
+    if others.is_empty() {
+        String::new()
+    } else if others.iter().all(|other| other.1 != from.1) {
+        square[..1].to_string()
+    } else if others.iter().all(|other| other.0 != from.0) {
+        square[1..].to_string()
+    } else {
+        square
+    }
+}
+
+/// ## san_to_move
+/// Finds the move given in Standard Algebraic Notation among the legal moves of the game.
+pub fn san_to_move(game: &Game, san: &str) -> Result<SanMove, String> {
+    let stripped = san.trim_end_matches(|c| "+#!?".contains(c));
+    let moves = legal_moves(game);
+
+    // Castling is given by the king moving two files
+    if stripped == "O-O" || stripped == "0-0" || stripped == "O-O-O" || stripped == "0-0-0" {
+        let file = if stripped.len() == 3 { 6 } else { 2 };
+        return moves
+            .into_iter()
+            .find(|(from, to)| {
+                game.board[from.0][from.1] == Piece::King(game.current_turn)
+                    && to.1 == file
+                    && from.1 == 4
+            })
+            .map(|(from, to)| (from, to, None))
+            .ok_or(format!("Illegal move {}", san));
+    }
+
+    let mut chars: Vec<char> = stripped.chars().filter(|c| *c != 'x' && *c != '-').collect();
+    let piece = match chars.first() {
+        Some('K') => Piece::King(game.current_turn),
+        Some('Q') => Piece::Queen(game.current_turn),
+        Some('R') => Piece::Rook(game.current_turn),
+        Some('B') => Piece::Bishop(game.current_turn),
+        Some('N') => Piece::Knight(game.current_turn),
+        _ => Piece::Pawn(game.current_turn),
+    };
+    if piece != Piece::Pawn(game.current_turn) {
+        chars.remove(0);
+    }
+
+    // Promotions are written as "e8=Q", sometimes without the equals sign or with a lowercase letter
+    let mut promotion = None;
+    let promotes = chars.len() >= 2 && (chars[chars.len() - 2] == '=' || chars[chars.len() - 2].is_ascii_digit());
+    if piece == Piece::Pawn(game.current_turn) && promotes {
+        if let Some(c) = chars.last().filter(|c| "QRBNqrbn".contains(**c)).copied() {
+            promotion = Some(c.to_ascii_lowercase());
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+    }
+
+    if chars.len() < 2 {
+        return Err(format!("Invalid move {}", san));
+    }
+    let target: String = chars[chars.len() - 2..].iter().collect();
+    let to = string_to_square(&target).ok_or(format!("Invalid move {}", san))?;
+    let disambiguation = &chars[..chars.len() - 2];
+
+    let candidates: Vec<((usize, usize), (usize, usize))> = moves
+        .into_iter()
+        .filter(|(from, target)| {
+            *target == to
+                && game.board[from.0][from.1] == piece
+                && disambiguation.iter().all(|c| square_to_string(*from).contains(*c))
+        })
+        .collect();
+    match candidates.as_slice() {
+        [(from, to)] => Ok((*from, *to, promotion)),
+        [] => Err(format!("Illegal move {}", san)),
+        _ => Err(format!("Ambiguous move {}", san)),
+    }
+}
+
+/// ## variation
+/// Converts moves played from the position given by a FEN string to numbered SAN, e.g. "12... Nf6 13. c4".
+/// Stops at the first illegal move.
+pub fn variation(fen: &str, moves: &[SanMove]) -> Vec<String> {
+    let mut game = game_from_fen(fen);
+    let mut tokens = Vec::new();
+    for (from, to, promotion) in moves {
+        if !legal_moves(&game).contains(&(*from, *to)) {
+            break;
+        }
+        let before = game_from_fen(&game.get_fen());
+        game.select_promotion(promotion.unwrap_or('q'));
+        game.take_turn(move_to_string(*from, *to));
+        let record = MoveRecord::new(&before, *from, *to, &game);
+        match record.colour() {
+            Colour::White => tokens.push(format!("{}. {}", record.number, record.san)),
+            Colour::Black if tokens.is_empty() => tokens.push(format!("{}... {}", record.number, record.san)),
+            Colour::Black => tokens.push(record.san),
+        }
+    }
+    tokens
+}
+
+/// ## in_check
+/// Checks whether the king of the given colour is attacked on the given board.
+pub fn in_check(board: &[[Piece; 8]; 8], colour: Colour) -> bool {
+    for rank in 0..8 {
+        for file in 0..8 {
+            if board[rank][file] == Piece::King(colour) {
+                return is_attacked(board, (rank, file), opponent(colour));
+            }
+        }
+    }
+    false
+}
+
+/// ## is_attacked
+/// Checks whether a square is attacked by any piece of the given colour.
+pub fn is_attacked(board: &[[Piece; 8]; 8], square: (usize, usize), by: Colour) -> bool {
+    let piece_at = |rank: i32, file: i32| -> Option<Piece> {
+        if (0..8).contains(&rank) && (0..8).contains(&file) {
+            Some(board[rank as usize][file as usize])
+        } else {
+            None
+        }
+    };
+    let (rank, file) = (square.0 as i32, square.1 as i32);
+
+    // Pawns attack diagonally towards the opposing side, white pawns move towards rank index 0
+    let pawn_rank = match by {
+        Colour::White => rank + 1,
+        Colour::Black => rank - 1,
+    };
+    if [-1, 1]
+        .iter()
+        .any(|df| piece_at(pawn_rank, file + df) == Some(Piece::Pawn(by)))
+    {
+        return true;
+    }
+
+    let knight_jumps = [(1, 2), (2, 1), (2, -1), (1, -2), (-1, -2), (-2, -1), (-2, 1), (-1, 2)];
+    if knight_jumps
+        .iter()
+        .any(|(dr, df)| piece_at(rank + dr, file + df) == Some(Piece::Knight(by)))
+    {
+        return true;
+    }
+
+    let king_steps = [(1, 1), (1, 0), (1, -1), (0, -1), (-1, -1), (-1, 0), (-1, 1), (0, 1)];
+    if king_steps
+        .iter()
+        .any(|(dr, df)| piece_at(rank + dr, file + df) == Some(Piece::King(by)))
+    {
+        return true;
+    }
+
+    // Sliding pieces, the king steps alternate between diagonal and straight directions, odd ones are straight
+    for (i, (dr, df)) in king_steps.iter().enumerate() {
+        let (mut r, mut f) = (rank + dr, file + df);
+        while let Some(piece) = piece_at(r, f) {
+            if piece != Piece::Empty {
+                let straight = i % 2 == 1;
+                if piece == Piece::Queen(by)
+                    || (straight && piece == Piece::Rook(by))
+                    || (!straight && piece == Piece::Bishop(by))
+                {
+                    return true;
+                }
+                break;
+            }
+            r += dr;
+            f += df;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disambiguation() {
+        let knights = game_from_fen("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1");
+        assert_eq!(san_to_move(&knights, "Nbd2"), Ok(((7, 1), (6, 3), None)));
+        assert_eq!(san_to_move(&knights, "Nfd2"), Ok(((5, 5), (6, 3), None)));
+        assert!(san_to_move(&knights, "Nd2").unwrap_err().starts_with("Ambiguous"));
+
+        let rooks = game_from_fen("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1");
+        assert_eq!(san_to_move(&rooks, "R1a3"), Ok(((7, 0), (5, 0), None)));
+        assert_eq!(san_to_move(&rooks, "R5a3+"), Ok(((3, 0), (5, 0), None)));
+        assert!(san_to_move(&rooks, "Ra3").unwrap_err().starts_with("Ambiguous"));
+    }
+
+    #[test]
+    fn promotion() {
+        let game = game_from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(san_to_move(&game, "a8=Q+"), Ok(((1, 0), (0, 0), Some('q'))));
+        assert_eq!(san_to_move(&game, "a8=q"), Ok(((1, 0), (0, 0), Some('q'))));
+        assert_eq!(san_to_move(&game, "a8N"), Ok(((1, 0), (0, 0), Some('n'))));
+        assert_eq!(san_to_move(&game, "a8=b"), Ok(((1, 0), (0, 0), Some('b'))));
+    }
+
+    #[test]
+    fn castling() {
+        let game = game_from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        assert_eq!(san_to_move(&game, "O-O"), Ok(((7, 4), (7, 6), None)));
+        assert_eq!(san_to_move(&game, "0-0-0"), Ok(((7, 4), (7, 2), None)));
+        assert_eq!(san_to_move(&game_from_fen("r3k2r/8/8/8/8/8/8/R3K2R b kq - 0 1"), "O-O-O"), Ok(((0, 4), (0, 2), None)));
+    }
+}
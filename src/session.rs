@@ -0,0 +1,105 @@
+use super::*;
+
+/// ## Session
+/// Everything needed to continue where the last session left off.
+/// Sessions are stored as PGN games with variations and a few extra tags.
+pub struct Session {
+    pub history: MoveTree,
+    pub cursor: Path,
+    pub promotion: char, // As passed to Game::select_promotion
+}
+
+impl Session {
+    /// Writes a session to a file, creating its directory if needed.
+    /// Takes the parts of the session by reference so that the application keeps its history while saving.
+    pub fn save(path: &path::Path, history: &MoveTree, cursor: &[usize], promotion: char) -> std::io::Result<()> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let cursor: Vec<String> = cursor.iter().map(|i| i.to_string()).collect();
+        let tags = [
+            ("Cursor", cursor.join(" ")),
+            ("Promotion", promotion.to_string()),
+        ];
+        fs::write(path, pgn::write_with_variations(history, pgn::result(history), &tags))
+    }
+
+    /// Reads a session from a file. Missing and unreadable files give None.
+    pub fn load(path: &path::Path) -> Option<Session> {
+        let text = fs::read_to_string(path).ok()?;
+        let history = pgn::read(&text).ok()?;
+        let tags = pgn::read_tags(&text).ok()?;
+        let tag = |name: &str| {
+            tags.iter()
+                .find(|(tag, _value)| tag == name)
+                .map(|(_tag, value)| value.clone())
+        };
+
+        // Fall back to the end of the main line if the cursor doesn't fit the history
+        let cursor: Path = tag("Cursor")
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(|i| i.parse().ok())
+            .collect::<Option<Path>>()
+            .filter(|cursor| cursor.is_empty() || history.node(cursor).is_some())
+            .unwrap_or_else(|| history.line_end(&[]));
+        let promotion = tag("Promotion")
+            .and_then(|promotion| promotion.chars().next())
+            .filter(|c| "qrbn".contains(*c))
+            .unwrap_or('q');
+
+        Some(Session {
+            history,
+            cursor,
+            promotion,
+        })
+    }
+
+    /// Checks whether the session holds a game worth resuming, i.e. one that is started but not finished.
+    pub fn is_unfinished(&self) -> bool {
+        !self.history.children.is_empty() && pgn::result(&self.history) == "*"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut history = pgn::read("1. e4 e5 2. Nf3 (2. f4 exf4) 2... Nc6 *").unwrap();
+        let cursor = vec![0, 0, 1, 0];
+        assert!(history.node(&cursor).is_some());
+        let path = std::env::temp_dir().join(format!("chess-session-{}.pgn", std::process::id()));
+        Session::save(&path, &history, &cursor, 'n').unwrap();
+
+        let session = Session::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(session.cursor, cursor);
+        assert_eq!(session.promotion, 'n');
+        assert!(session.is_unfinished());
+        assert_eq!(
+            pgn::write_with_variations(&session.history, "*", &[]),
+            pgn::write_with_variations(&history, "*", &[])
+        );
+
+        // A cursor that doesn't fit the history falls back to the end of the main line
+        history = pgn::read("1. d4 *").unwrap();
+        Session::save(&path, &history, &[3, 1], 'q').unwrap();
+        let session = Session::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(session.cursor, history.line_end(&[]));
+    }
+
+    #[test]
+    fn drawn_games_are_finished() {
+        let session = |pgn: &str| Session {
+            history: pgn::read(pgn).unwrap(),
+            cursor: Vec::new(),
+            promotion: 'q',
+        };
+        assert!(session("1. Nf3 Nf6 2. Ng1 Ng8 3. Nf3 Nf6 4. Ng1 *").is_unfinished());
+        assert!(!session("1. Nf3 Nf6 2. Ng1 Ng8 3. Nf3 Nf6 4. Ng1 Ng8 *").is_unfinished());
+        assert!(!session("[FEN \"4k3/8/8/8/8/8/8/R3K3 w - - 99 80\"]\n\n80. Ra2 *").is_unfinished());
+    }
+}
@@ -0,0 +1,129 @@
+use super::*;
+use ggez::graphics::Color;
+
+/// ## Diagram
+/// What to draw besides the pieces when exporting a position as an SVG diagram.
+/// Squares are (rank, file) tuples like the ones passed to move_to_string.
+#[derive(Default)]
+pub struct Diagram {
+    pub flipped: bool, // Black at the bottom
+    pub coordinates: bool,
+    pub last_move: Option<((usize, usize), (usize, usize))>,
+    pub selected: Option<(usize, usize)>,
+}
+
+/// ## board
+/// Renders a board as an SVG image the way draw::board shows it, one tile being GRID_CELL_SIZE units wide.
+/// Pieces are drawn as Unicode chess glyphs so that the diagram stays a vector image when scaled.
+pub fn board(board: &[[Piece; 8]; 8], diagram: &Diagram) -> String {
+    let size = GRID_CELL_SIZE.0 as usize;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\">\n",
+        size * 8
+    );
+
+    for (rank, pieces) in board.iter().enumerate() {
+        for (file, piece) in pieces.iter().enumerate() {
+            let (row, col) = flip_square((rank, file), diagram.flipped);
+            let (x, y) = (col * size, row * size);
+            let light = (rank + file) % 2 == 0;
+            svg.push_str(&rectangle(x, y, size, if light { WHITE } else { BLACK }));
+
+            if let Some((from, to)) = diagram.last_move {
+                if (rank, file) == from || (rank, file) == to {
+                    svg.push_str(&rectangle(x, y, size, LAST_MOVE_COLOR));
+                }
+            }
+            if diagram.selected == Some((rank, file)) {
+                svg.push_str(&rectangle(x, y, size, SELECTED_COLOR));
+            }
+
+            // Coordinates go along the left and bottom edges, in the colour of the other tiles
+            if diagram.coordinates {
+                let contrast = if light { BLACK } else { WHITE };
+                let square = square_to_string((rank, file));
+                if col == 0 {
+                    svg.push_str(&text(x + 4, y + 18, 16, "start", contrast, &square[1..]));
+                }
+                if row == 7 {
+                    svg.push_str(&text(x + size - 4, y + size - 5, 16, "end", contrast, &square[..1]));
+                }
+            }
+
+            // White pieces are outlined glyphs filled in white, so both colours use the solid glyphs
+            if let Some(glyph) = glyph(*piece) {
+                let (fill, stroke) = match get_colour(*piece) {
+                    Some(Colour::White) => ("#ffffff", "#000000"),
+                    _ => ("#000000", "none"),
+                };
+                svg.push_str(&format!(
+                    "  <text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\" \
+                     fill=\"{}\" stroke=\"{}\" stroke-width=\"1.5\" font-family=\"DejaVu Sans, Segoe UI Symbol, sans-serif\">{}</text>\n",
+                    x + size / 2,
+                    y + size / 2,
+                    size * 3 / 4,
+                    fill,
+                    stroke,
+                    glyph
+                ));
+            }
+        }
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// The solid Unicode chess glyph of a piece, whatever its colour.
+fn glyph(piece: Piece) -> Option<char> {
+    match piece {
+        Piece::King(_) => Some('\u{265A}'),
+        Piece::Queen(_) => Some('\u{265B}'),
+        Piece::Rook(_) => Some('\u{265C}'),
+        Piece::Bishop(_) => Some('\u{265D}'),
+        Piece::Knight(_) => Some('\u{265E}'),
+        Piece::Pawn(_) => Some('\u{265F}'),
+        Piece::Empty => None,
+    }
+}
+
+/// A tile sized rectangle in the given colour.
+fn rectangle(x: usize, y: usize, size: usize, color: Color) -> String {
+    format!(
+        "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {}/>\n",
+        x,
+        y,
+        size,
+        size,
+        paint("fill", color)
+    )
+}
+
+/// A line of text anchored at the given point.
+fn text(x: usize, y: usize, size: usize, anchor: &str, color: Color, string: &str) -> String {
+    format!(
+        "  <text x=\"{}\" y=\"{}\" font-size=\"{}\" text-anchor=\"{}\" font-family=\"sans-serif\" font-weight=\"bold\" {}>{}</text>\n",
+        x,
+        y,
+        size,
+        anchor,
+        paint("fill", color),
+        string
+    )
+}
+
+/// SVG attributes painting with a colour, adding an opacity for translucent colours.
+fn paint(attribute: &str, color: Color) -> String {
+    let channel = |value: f32| (value * 255.0).round() as u8;
+    let mut paint = format!(
+        "{}=\"#{:02x}{:02x}{:02x}\"",
+        attribute,
+        channel(color.r),
+        channel(color.g),
+        channel(color.b)
+    );
+    if color.a < 1.0 {
+        paint.push_str(&format!(" {}-opacity=\"{}\"", attribute, color.a));
+    }
+    paint
+}
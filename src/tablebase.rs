@@ -0,0 +1,176 @@
+use super::*;
+use san::SanMove;
+use shakmaty::{fen::Fen, CastlingMode, Chess, Position};
+use shakmaty_syzygy::Wdl;
+
+/// ## Tablebase
+/// Local Syzygy endgame tables, giving the exact result of positions with few enough pieces.
+pub struct Tablebase {
+    tables: shakmaty_syzygy::Tablebase<Chess>,
+}
+
+/// ## Probe
+/// What the tables say about a position: its result with best play and the result of each legal move.
+/// DTZ counts the plies to the next capture or pawn move that keeps the result, which is what winning
+/// within the fifty move rule depends on.
+pub struct Probe {
+    pub fen: String, // Position probed
+    pub wdl: Wdl, // Result for the side to move
+    pub dtz: i32,
+    pub moves: Vec<(SanMove, Wdl, i32)>, // Legal moves with their result and DTZ for the mover, counting the move, best first
+}
+
+impl Tablebase {
+    /// Opens the WDL and DTZ tables in a directory.
+    pub fn open(dir: &path::Path) -> Result<Tablebase, String> {
+        let mut tables = shakmaty_syzygy::Tablebase::new();
+        let added = tables.add_directory(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        if added == 0 {
+            return Err(format!("{}: no Syzygy tables", dir.display()));
+        }
+        Ok(Tablebase { tables })
+    }
+
+    /// Probes the position given by a FEN string and every move from it.
+    /// None if the position has too many pieces, castling rights or isn't covered by the tables.
+    pub fn probe(&self, fen: &str) -> Option<Probe> {
+        let position: Chess = fen.parse::<Fen>().ok()?.into_position(CastlingMode::Standard).ok()?;
+        if position.board().occupied().count() > self.tables.max_pieces() {
+            return None;
+        }
+        let wdl = self.tables.probe_wdl_after_zeroing(&position).ok()?;
+        let dtz = self.tables.probe_dtz(&position).ok()?.ignore_rounding().0;
+
+        let mut moves = Vec::new();
+        for m in position.legal_moves() {
+            let after = position.clone().play(m).ok()?;
+            let result = flip(self.tables.probe_wdl_after_zeroing(&after).ok()?);
+            // The DTZ of the opponent after the move, plus the move itself. A winning capture or pawn move
+            // reaches the next zeroing move at once, while the loser still has the opponent's count ahead
+            let plies = match rank(result) {
+                0 => 0,
+                r if r > 0 && m.is_zeroing() => 1,
+                _ => self.tables.probe_dtz(&after).ok()?.ignore_rounding().0.abs() + 1,
+            };
+            let found = uci::uci_to_move(&m.to_uci(CastlingMode::Standard).to_string())?;
+            moves.push((found, result, plies));
+        }
+        order(&mut moves);
+
+        Some(Probe {
+            fen: fen.to_string(),
+            wdl,
+            dtz,
+            moves,
+        })
+    }
+}
+
+impl Probe {
+    /// The moves keeping the best result the quickest, i.e. the DTZ-optimal moves.
+    pub fn best_moves(&self) -> Vec<SanMove> {
+        let key = |(_m, result, plies): &(SanMove, Wdl, i32)| (*result, *plies);
+        match self.moves.first() {
+            Some(best) => self
+                .moves
+                .iter()
+                .filter(|m| key(m) == key(best))
+                .map(|(m, _result, _plies)| *m)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// ## describe
+/// The result in words. Cursed wins and blessed losses are drawn by the fifty move rule.
+pub fn describe(wdl: Wdl) -> &'static str {
+    match wdl {
+        Wdl::Win => "Win",
+        Wdl::CursedWin => "Cursed win",
+        Wdl::Draw => "Draw",
+        Wdl::BlessedLoss => "Blessed loss",
+        Wdl::Loss => "Loss",
+    }
+}
+
+/// Sorts moves with better results first, then the quickest wins and the slowest losses.
+fn order(moves: &mut [(SanMove, Wdl, i32)]) {
+    moves.sort_by_key(|(_m, result, plies)| (-rank(*result), if rank(*result) > 0 { *plies } else { -*plies }));
+}
+
+/// Orders results from the worst to the best.
+fn rank(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Loss => -2,
+        Wdl::BlessedLoss => -1,
+        Wdl::Draw => 0,
+        Wdl::CursedWin => 1,
+        Wdl::Win => 2,
+    }
+}
+
+/// The result for the other side.
+fn flip(wdl: Wdl) -> Wdl {
+    match wdl {
+        Wdl::Loss => Wdl::Win,
+        Wdl::BlessedLoss => Wdl::CursedWin,
+        Wdl::Draw => Wdl::Draw,
+        Wdl::CursedWin => Wdl::BlessedLoss,
+        Wdl::Win => Wdl::Loss,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESULTS: [Wdl; 5] = [Wdl::Loss, Wdl::BlessedLoss, Wdl::Draw, Wdl::CursedWin, Wdl::Win];
+
+    #[test]
+    fn ranks() {
+        for pair in RESULTS.windows(2) {
+            assert!(rank(pair[0]) < rank(pair[1]));
+        }
+        assert_eq!(rank(Wdl::Draw), 0);
+    }
+
+    #[test]
+    fn flips() {
+        for wdl in RESULTS.iter() {
+            assert_eq!(rank(flip(*wdl)), -rank(*wdl));
+            assert_eq!(flip(flip(*wdl)), *wdl);
+        }
+    }
+
+    #[test]
+    fn move_ordering() {
+        let mut moves = vec![
+            (((6, 0), (5, 0), None), Wdl::Loss, 3),
+            (((6, 1), (5, 1), None), Wdl::Draw, 0),
+            (((6, 2), (5, 2), None), Wdl::Win, 9),
+            (((6, 3), (5, 3), None), Wdl::Loss, 21),
+            (((6, 4), (5, 4), None), Wdl::CursedWin, 1),
+            (((6, 5), (5, 5), None), Wdl::Win, 1),
+            (((6, 6), (5, 6), None), Wdl::BlessedLoss, 5),
+        ];
+        order(&mut moves);
+        let files: Vec<usize> = moves.iter().map(|(((_rank, file), _to, _promotion), _wdl, _plies)| *file).collect();
+        // Quickest win, slower win, cursed win, draw, blessed loss, then the slowest loss first
+        assert_eq!(files, vec![5, 2, 4, 1, 6, 3, 0]);
+    }
+
+    #[test]
+    fn best_moves() {
+        let probe = |moves: Vec<(SanMove, Wdl, i32)>| Probe {
+            fen: "4k3/8/8/8/8/8/8/4K2Q w - - 0 1".to_string(),
+            wdl: Wdl::Win,
+            dtz: 1,
+            moves,
+        };
+        let (a, b, c) = (((7, 7), (0, 7), None), ((7, 7), (4, 4), None), ((7, 4), (6, 4), None));
+        assert_eq!(probe(vec![(a, Wdl::Win, 1), (b, Wdl::Win, 1), (c, Wdl::Win, 5)]).best_moves(), vec![a, b]);
+        assert_eq!(probe(vec![(c, Wdl::Win, 5), (a, Wdl::Draw, 0)]).best_moves(), vec![c]);
+        assert!(probe(Vec::new()).best_moves().is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+use super::*;
+
+/// ## board
+/// Renders a board as text, one rank per line with the rank number in front and the files below.
+/// Pieces are FEN letters or, if unicode is set, Unicode chess glyphs. Empty squares are dots.
+pub fn board(board: &[[Piece; 8]; 8], unicode: bool, flipped: bool) -> String {
+    let mut text = String::new();
+    for row in 0..8 {
+        let rank = flip_square((row, 0), flipped).0;
+        text.push_str(&square_to_string((rank, 0))[1..]);
+        for col in 0..8 {
+            let (rank, file) = flip_square((row, col), flipped);
+            text.push(' ');
+            text.push(piece_char(board[rank][file], unicode));
+        }
+        text.push('\n');
+    }
+
+    text.push(' ');
+    for col in 0..8 {
+        text.push(' ');
+        text.push_str(&square_to_string(flip_square((0, col), flipped))[..1]);
+    }
+    text.push('\n');
+    text
+}
+
+/// The character a piece is shown as, see board.
+fn piece_char(piece: Piece, unicode: bool) -> char {
+    let (letter, white, black) = match piece {
+        Piece::King(_) => ('k', '\u{2654}', '\u{265A}'),
+        Piece::Queen(_) => ('q', '\u{2655}', '\u{265B}'),
+        Piece::Rook(_) => ('r', '\u{2656}', '\u{265C}'),
+        Piece::Bishop(_) => ('b', '\u{2657}', '\u{265D}'),
+        Piece::Knight(_) => ('n', '\u{2658}', '\u{265E}'),
+        Piece::Pawn(_) => ('p', '\u{2659}', '\u{265F}'),
+        Piece::Empty => ('.', '\u{00B7}', '\u{00B7}'),
+    };
+    match (get_colour(piece), unicode) {
+        (Some(Colour::White), true) => white,
+        (_, true) => black,
+        (Some(Colour::White), false) => letter.to_ascii_uppercase(),
+        (_, false) => letter,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_position() {
+        let expected = "\
+8 r n b q k b n r
+7 p p p p p p p p
+6 . . . . . . . .
+5 . . . . . . . .
+4 . . . . . . . .
+3 . . . . . . . .
+2 P P P P P P P P
+1 R N B Q K B N R
+  a b c d e f g h
+";
+        assert_eq!(board(&Game::new().board, false, false), expected);
+    }
+
+    #[test]
+    fn flipped_start_position() {
+        let expected = "\
+1 R N B K Q B N R
+2 P P P P P P P P
+3 . . . . . . . .
+4 . . . . . . . .
+5 . . . . . . . .
+6 . . . . . . . .
+7 p p p p p p p p
+8 r n b k q b n r
+  h g f e d c b a
+";
+        assert_eq!(board(&Game::new().board, false, true), expected);
+    }
+
+    #[test]
+    fn unicode_start_position() {
+        let expected = "\
+8 ♜ ♞ ♝ ♛ ♚ ♝ ♞ ♜
+7 ♟ ♟ ♟ ♟ ♟ ♟ ♟ ♟
+6 · · · · · · · ·
+5 · · · · · · · ·
+4 · · · · · · · ·
+3 · · · · · · · ·
+2 ♙ ♙ ♙ ♙ ♙ ♙ ♙ ♙
+1 ♖ ♘ ♗ ♕ ♔ ♗ ♘ ♖
+  a b c d e f g h
+";
+        assert_eq!(board(&Game::new().board, true, false), expected);
+    }
+
+    #[test]
+    fn flipped_unicode_start_position() {
+        let expected = "\
+1 ♖ ♘ ♗ ♔ ♕ ♗ ♘ ♖
+2 ♙ ♙ ♙ ♙ ♙ ♙ ♙ ♙
+3 · · · · · · · ·
+4 · · · · · · · ·
+5 · · · · · · · ·
+6 · · · · · · · ·
+7 ♟ ♟ ♟ ♟ ♟ ♟ ♟ ♟
+8 ♜ ♞ ♝ ♚ ♛ ♝ ♞ ♜
+  h g f e d c b a
+";
+        assert_eq!(board(&Game::new().board, true, true), expected);
+    }
+}
@@ -0,0 +1,251 @@
+use super::*;
+
+/// A path through the move tree, given as child indices starting from the start position.
+/// The empty path is the start position itself.
+pub type Path = Vec<usize>;
+
+/// ## MoveNode
+/// A move in the move tree together with the FEN string of the position after it.
+/// The first child is the main continuation, the others are variations.
+pub struct MoveNode {
+    pub fen: String,
+    pub record: MoveRecord,
+    pub children: Vec<MoveNode>,
+}
+
+/// ## Row
+/// A row of the history viewer. Holds up to one white and one black move,
+/// depth is zero for the main line and grows with each nested variation.
+pub struct Row {
+    pub number: usize,
+    pub depth: usize,
+    pub cells: [Option<Path>; 2],
+}
+
+/// ## MoveTree
+/// All moves played from the start position, including variations.
+pub struct MoveTree {
+    pub start_fen: String,
+    pub children: Vec<MoveNode>,
+}
+
+impl MoveTree {
+    /// Creates an empty move tree starting at the given position.
+    pub fn new(start_fen: String) -> MoveTree {
+        MoveTree {
+            start_fen,
+            children: Vec::new(),
+        }
+    }
+
+    /// The node at the end of the path, None for the start position or invalid paths.
+    pub fn node(&self, path: &[usize]) -> Option<&MoveNode> {
+        let (last, parent) = path.split_last()?;
+        self.children(parent)?.get(*last)
+    }
+
+    /// The moves that can follow the position at the end of the path.
+    pub fn children(&self, path: &[usize]) -> Option<&Vec<MoveNode>> {
+        let mut children = &self.children;
+        for i in path {
+            children = &children.get(*i)?.children;
+        }
+        Some(children)
+    }
+
+    fn children_mut(&mut self, path: &[usize]) -> Option<&mut Vec<MoveNode>> {
+        let mut children = &mut self.children;
+        for i in path {
+            children = &mut children.get_mut(*i)?.children;
+        }
+        Some(children)
+    }
+
+    /// FEN string of the position at the end of the path.
+    pub fn fen(&self, path: &[usize]) -> &str {
+        match self.node(path) {
+            Some(node) => &node.fen,
+            None => &self.start_fen,
+        }
+    }
+
+    /// The move leading up to the position at the end of the path.
+    pub fn record(&self, path: &[usize]) -> Option<&MoveRecord> {
+        self.node(path).map(|node| &node.record)
+    }
+
+    /// Checks whether no moves have been played after the position at the end of the path.
+    pub fn is_leaf(&self, path: &[usize]) -> bool {
+        self.children(path).is_none_or(|children| children.is_empty())
+    }
+
+    /// Path to the end of the line continuing from the given path, following main continuations.
+    pub fn line_end(&self, path: &[usize]) -> Path {
+        let mut path = path.to_vec();
+        while !self.is_leaf(&path) {
+            path.push(0);
+        }
+        path
+    }
+
+    /// Plays a move after the position at the end of the path and returns the path to it.
+    /// promotion is the piece a pawn reaching the last rank becomes, as passed to Game::select_promotion.
+    pub fn play(
+        &mut self,
+        path: &[usize],
+        from: (usize, usize),
+        to: (usize, usize),
+        promotion: Option<char>,
+    ) -> Path {
+        let before = game_from_fen(self.fen(path));
+        let mut game = game_from_fen(self.fen(path));
+        if let Some(c) = promotion {
+            game.select_promotion(c);
+        }
+        game.take_turn(move_to_string(from, to));
+        let record = MoveRecord::new(&before, from, to, &game);
+        self.add_move(path, game.get_fen(), record)
+    }
+
+    /// Adds a move after the position at the end of the path and returns the path to it.
+    /// A move that has already been played from that position is reused instead of added again,
+    /// otherwise the move becomes the main continuation if there is none or a new variation.
+    pub fn add_move(&mut self, path: &[usize], fen: String, record: MoveRecord) -> Path {
+        let children = self
+            .children_mut(path)
+            .expect("Moves are only added to existing positions");
+        let index = match children.iter().position(|node| {
+            node.record.from == record.from
+                && node.record.to == record.to
+                && node.record.promotion == record.promotion
+        }) {
+            Some(index) => index,
+            None => {
+                children.push(MoveNode {
+                    fen,
+                    record,
+                    children: Vec::new(),
+                });
+                children.len() - 1
+            }
+        };
+
+        let mut path = path.to_vec();
+        path.push(index);
+        path
+    }
+
+    /// Makes the line leading up to the path part of the main line and returns its new path.
+    pub fn promote(&mut self, path: &[usize]) -> Path {
+        for (depth, index) in path.iter().enumerate() {
+            if *index != 0 {
+                if let Some(children) = self.children_mut(&vec![0; depth]) {
+                    let node = children.remove(*index);
+                    children.insert(0, node);
+                }
+            }
+        }
+        vec![0; path.len()]
+    }
+
+    /// Deletes the innermost variation containing the path.
+    /// Returns the path to the position the variation branched from, or None on the main line.
+    pub fn delete_variation(&mut self, path: &[usize]) -> Option<Path> {
+        let depth = path.iter().rposition(|i| *i != 0)?;
+        self.children_mut(&path[..depth])?.remove(path[depth]);
+        Some(path[..depth].to_vec())
+    }
+
+    /// The moves leading up to the position at the end of the path, in order.
+    pub fn line(&self, path: &[usize]) -> Vec<&MoveNode> {
+        (1..=path.len()).filter_map(|length| self.node(&path[..length])).collect()
+    }
+
+    /// The moves of the main line, in order.
+    pub fn main_line(&self) -> Vec<&MoveNode> {
+        self.line(&self.line_end(&[]))
+    }
+
+    /// Lays out the tree as rows for the history viewer.
+    /// Variations are placed in the rows right after the move they are an alternative to.
+    pub fn rows(&self) -> Vec<Row> {
+        let mut rows = Vec::new();
+        if !self.children.is_empty() {
+            self.push_line(&mut rows, vec![0], 0);
+        }
+        rows
+    }
+
+    /// Pushes the rows of the line starting with the move at the end of the path.
+    fn push_line(&self, rows: &mut Vec<Row>, mut path: Path, depth: usize) {
+        let mut row: Option<Row> = None;
+        while let Some(node) = self.node(&path) {
+            match node.record.colour() {
+                Colour::White => {
+                    rows.extend(row.take());
+                    row = Some(Row {
+                        number: node.record.number,
+                        depth,
+                        cells: [Some(path.clone()), None],
+                    });
+                }
+                Colour::Black => match row.as_mut() {
+                    Some(r) if r.cells[1].is_none() && r.number == node.record.number => {
+                        r.cells[1] = Some(path.clone());
+                    }
+                    _ => {
+                        rows.extend(row.take());
+                        row = Some(Row {
+                            number: node.record.number,
+                            depth,
+                            cells: [None, Some(path.clone())],
+                        });
+                    }
+                },
+            }
+
+            // Variations are alternatives to the main continuation, so they follow its row
+            let (last, parent) = path.split_last().expect("Lines never start at the root");
+            let siblings = self.children(parent).map_or(0, |children| children.len());
+            if *last == 0 && siblings > 1 {
+                rows.extend(row.take());
+                for i in 1..siblings {
+                    let mut variation = parent.to_vec();
+                    variation.push(i);
+                    self.push_line(rows, variation, depth + 1);
+                }
+            }
+
+            path.push(0);
+        }
+        rows.extend(row);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takeback_keeps_variations() {
+        // 1. e4 e5 (1... c5)
+        let mut history = MoveTree::new(Game::new().get_fen());
+        let e4 = history.play(&[], (6, 4), (4, 4), None);
+        let e5 = history.play(&e4, (1, 4), (3, 4), None);
+        let c5 = history.play(&e4, (1, 2), (3, 2), None);
+        assert_eq!((e5.clone(), c5.clone()), (vec![0, 0], vec![0, 1]));
+
+        // Taking back 1... e5 moves back to the position after 1. e4, playing it again finds it in its place
+        let (_last, back) = e5.split_last().unwrap();
+        assert_eq!(back, &e4[..]);
+        assert_eq!(history.play(back, (1, 4), (3, 4), None), e5);
+        assert_eq!(history.line_end(&[]), e5);
+        assert_eq!(history.children(&e4).unwrap().len(), 2);
+        assert_eq!(history.record(&c5).unwrap().to, (3, 2));
+
+        // Another move is a new variation after the earlier ones
+        let d5 = history.play(back, (1, 3), (3, 3), None);
+        assert_eq!(d5, vec![0, 2]);
+        assert_eq!(history.line_end(&[]), e5);
+    }
+}
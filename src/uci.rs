@@ -0,0 +1,425 @@
+use super::*;
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time an engine may take beyond the requested move time before it is considered hung.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// ## Event
+/// What an engine has to report when it is polled.
+pub enum Event {
+    Info(Info), // Progress of the running search
+    BestMove(String), // The move found by the last search, in UCI notation such as "e7e8q"
+    Crashed,
+    TimedOut,
+}
+
+/// ## Score
+/// An engine's evaluation of a position. UCI engines score from the side to move.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Score {
+    Centipawns(i32),
+    Mate(i32), // Moves until mate, negative when getting mated
+}
+
+impl Score {
+    /// The score from white's side, given the side to move in the scored position.
+    pub fn for_white(self, side_to_move: Colour) -> Score {
+        match (self, side_to_move) {
+            (score, Colour::White) => score,
+            (Score::Centipawns(cp), Colour::Black) => Score::Centipawns(-cp),
+            (Score::Mate(moves), Colour::Black) => Score::Mate(-moves),
+        }
+    }
+
+    /// Expected share of the points for the side the score is for, from 0.0 to 1.0.
+    pub fn expectation(self) -> f32 {
+        match self {
+            Score::Centipawns(cp) => 1.0 / (1.0 + 10f32.powf(-cp as f32 / 400.0)),
+            Score::Mate(moves) if moves > 0 => 1.0,
+            Score::Mate(_) => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    /// Pawns with a sign, e.g. "+0.35", or moves to mate, e.g. "#3" and "#-3".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Score::Centipawns(cp) => write!(f, "{:+.2}", *cp as f32 / 100.0),
+            Score::Mate(moves) => write!(f, "#{}", moves),
+        }
+    }
+}
+
+/// ## Info
+/// A line of the running search, as reported by the engine's "info" output.
+#[derive(Clone)]
+pub struct Info {
+    pub depth: usize,
+    pub multipv: usize, // Rank of the line among the best lines, starting at 1
+    pub score: Score,
+    pub pv: Vec<String>, // Principal variation in UCI notation
+}
+
+impl Info {
+    /// Reads an info line, None for info lines without a score.
+    fn parse(line: &str) -> Option<Info> {
+        let mut words = line.split_whitespace();
+        let mut info = Info {
+            depth: 0,
+            multipv: 1,
+            score: Score::Centipawns(0),
+            pv: Vec::new(),
+        };
+        let mut scored = false;
+        while let Some(word) = words.next() {
+            match word {
+                "depth" => info.depth = words.next()?.parse().ok()?,
+                "multipv" => info.multipv = words.next()?.parse().ok()?,
+                "score" => {
+                    let value = |words: &mut std::str::SplitWhitespace| words.next()?.parse().ok();
+                    info.score = match words.next()? {
+                        "cp" => Score::Centipawns(value(&mut words)?),
+                        "mate" => Score::Mate(value(&mut words)?),
+                        _ => return None,
+                    };
+                    scored = true;
+                }
+                "pv" => info.pv = words.by_ref().map(|m| m.to_string()).collect(),
+                // The rest of the line is free text
+                "string" => return None,
+                _ => (),
+            }
+        }
+        if scored {
+            Some(info)
+        } else {
+            None
+        }
+    }
+}
+
+/// ## Engine
+/// A UCI engine running as a subprocess.
+/// Its output is read on a separate thread, so nothing here waits for the engine
+/// and the engine can be polled from EventHandler::update.
+pub struct Engine {
+    pub name: String, // Taken from the engine's "id name" once it has answered
+    child: Child,
+    stdin: ChildStdin,
+    lines: mpsc::Receiver<String>,
+    searching: bool,
+    deadline: Option<Instant>, // Time the running search has to be finished by, None for infinite searches
+    abandoned: usize, // Searches that were stopped but haven't sent their best move yet, their output is ignored
+    failed: bool,
+    timeout: Duration,
+}
+
+impl Engine {
+    /// Starts the engine binary at the given path and tells it to use UCI.
+    pub fn start(path: &path::Path) -> Result<Engine, String> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let stdin = child.stdin.take().ok_or("No engine input")?;
+        let stdout = child.stdout.take().ok_or("No engine output")?;
+
+        // The channel is closed when the engine's output ends, i.e. when it exits
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = Engine {
+            name: path
+                .file_stem()
+                .map_or("Engine".to_string(), |name| name.to_string_lossy().to_string()),
+            child,
+            stdin,
+            lines,
+            searching: false,
+            deadline: None,
+            abandoned: 0,
+            failed: false,
+            timeout: TIMEOUT,
+        };
+        engine.send("uci");
+        engine.send("isready");
+        Ok(engine)
+    }
+
+    /// Sends a command to the engine. Failing to write means the engine is gone.
+    fn send(&mut self, command: &str) {
+        if writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .is_err()
+        {
+            self.failed = true;
+        }
+    }
+
+    /// Sets the position reached by playing the moves, given in UCI notation, from the start FEN.
+    fn position(&mut self, start_fen: &str, moves: &[String]) {
+        let mut position = format!("position fen {}", start_fen);
+        if !moves.is_empty() {
+            position.push_str(" moves ");
+            position.push_str(&moves.join(" "));
+        }
+        self.send(&position);
+    }
+
+    /// Starts searching the position reached by playing the moves, given in UCI notation, from the start FEN.
+    /// The best move is reported by poll once the move time has passed.
+    pub fn go(&mut self, start_fen: &str, moves: &[String], movetime: Duration) {
+        self.stop();
+        self.position(start_fen, moves);
+        self.send(&format!("go movetime {}", movetime.as_millis()));
+        self.searching = true;
+        self.deadline = Some(Instant::now() + movetime + self.timeout);
+    }
+
+    /// Starts analysing the position reached by playing the moves until stopped, replacing any running search.
+    /// The lines found are reported by poll as the search goes deeper.
+    pub fn analyse(&mut self, start_fen: &str, moves: &[String]) {
+        self.stop();
+        self.position(start_fen, moves);
+        self.send("go infinite");
+        self.searching = true;
+        self.deadline = None;
+    }
+
+    /// Sets a UCI option, such as "MultiPV". Takes effect from the next search.
+    pub fn set_option(&mut self, name: &str, value: &str) {
+        self.send(&format!("setoption name {} value {}", name, value));
+    }
+
+    /// Stops the running search. Nothing more is reported about it, not even its best move.
+    pub fn stop(&mut self) {
+        if self.searching {
+            self.send("stop");
+            self.searching = false;
+            self.deadline = None;
+            self.abandoned += 1;
+        }
+    }
+
+    /// Checks whether a search has been started and its best move not yet reported.
+    pub fn is_searching(&self) -> bool {
+        self.searching
+    }
+
+    /// Reads everything the engine has written since the last poll.
+    /// Reports the progress and the best move of the running search, and whether the engine has crashed or hung.
+    pub fn poll(&mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+        loop {
+            match self.lines.try_recv() {
+                Ok(line) => {
+                    let mut words = line.split_whitespace();
+                    match words.next() {
+                        Some("bestmove") if self.abandoned > 0 => self.abandoned -= 1,
+                        Some("bestmove") if self.searching => {
+                            self.searching = false;
+                            self.deadline = None;
+                            events.push(Event::BestMove(words.next().unwrap_or("0000").to_string()));
+                        }
+                        Some("info") if self.abandoned == 0 && self.searching => {
+                            events.extend(Info::parse(&line).map(Event::Info));
+                        }
+                        Some("id") if words.next() == Some("name") => {
+                            self.name = words.collect::<Vec<&str>>().join(" ");
+                        }
+                        _ => (),
+                    }
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.failed = true;
+                    break;
+                }
+            }
+        }
+
+        if self.failed {
+            events.push(Event::Crashed);
+        } else if self.deadline.is_some_and(|deadline| Instant::now() > deadline) {
+            self.searching = false;
+            self.deadline = None;
+            events.push(Event::TimedOut);
+        }
+        events
+    }
+}
+
+impl Drop for Engine {
+    /// Asks the engine to quit, and makes sure it does.
+    fn drop(&mut self) {
+        self.send("quit");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// ## move_to_uci
+/// Converts a recorded move to UCI notation, e.g. "e2e4" or "e7e8q".
+pub fn move_to_uci(record: &MoveRecord) -> String {
+    let mut uci = square_to_string(record.from) + &square_to_string(record.to);
+    if let Some(piece) = record.promotion {
+        uci.push(promotion_char(piece));
+    }
+    uci
+}
+
+/// ## uci_to_move
+/// Reads a move in UCI notation. The move is not checked to be legal.
+pub fn uci_to_move(uci: &str) -> Option<san::SanMove> {
+    if !uci.is_ascii() || !(4..=5).contains(&uci.len()) {
+        return None;
+    }
+    let from = string_to_square(&uci[0..2])?;
+    let to = string_to_square(&uci[2..4])?;
+    let promotion = match uci[4..].chars().next() {
+        Some(c) if "qrbn".contains(c) => Some(c),
+        Some(_) => return None,
+        None => None,
+    };
+    Some((from, to, promotion))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// Starts a stand-in engine: a shell script answering UCI commands.
+    fn script(name: &str, script: &str) -> Engine {
+        let path = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        fs::write(&path, format!("#!/bin/sh\n{}", script)).unwrap();
+        Command::new("chmod").arg("+x").arg(&path).status().unwrap();
+        Engine::start(&path).unwrap()
+    }
+
+    /// Polls the engine until it reports something other than search progress, failing after a few seconds.
+    /// Returns the progress reported before it as well.
+    fn wait(engine: &mut Engine) -> (Vec<Info>, Event) {
+        let start = Instant::now();
+        let mut infos = Vec::new();
+        while start.elapsed() < Duration::from_secs(5) {
+            for event in engine.poll() {
+                match event {
+                    Event::Info(info) => infos.push(info),
+                    event => return (infos, event),
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("The engine reported nothing");
+    }
+
+    #[test]
+    fn best_move() {
+        let mut engine = script(
+            "best-move",
+            "while read line; do\n\
+             case \"$line\" in\n\
+             uci) echo 'id name Stand-in'; echo uciok;;\n\
+             isready) echo readyok;;\n\
+             go*) echo 'info depth 1 score cp 20 pv e7e5'; echo 'bestmove e7e5';;\n\
+             quit) exit 0;;\n\
+             esac\n\
+             done\n",
+        );
+        engine.go(START_FEN, &["e2e4".to_string()], Duration::from_millis(10));
+        assert!(engine.is_searching());
+        let (infos, event) = wait(&mut engine);
+        match event {
+            Event::BestMove(bestmove) => assert_eq!(bestmove, "e7e5"),
+            _ => panic!("Expected a best move"),
+        }
+        assert_eq!(infos.len(), 1);
+        assert!(infos[0].depth == 1 && infos[0].score == Score::Centipawns(20) && infos[0].pv == ["e7e5"]);
+        assert!(!engine.is_searching());
+        assert_eq!(engine.name, "Stand-in");
+    }
+
+    #[test]
+    fn crash() {
+        let mut engine = script("crash", "read line\nexit 1\n");
+        engine.go(START_FEN, &[], Duration::from_millis(10));
+        assert!(matches!(wait(&mut engine).1, Event::Crashed));
+    }
+
+    #[test]
+    fn timeout() {
+        let mut engine = script("timeout", "while read line; do :; done\n");
+        engine.timeout = Duration::from_millis(50);
+        engine.go(START_FEN, &[], Duration::from_millis(10));
+        assert!(matches!(wait(&mut engine).1, Event::TimedOut));
+    }
+
+    #[test]
+    fn stopped_search() {
+        // Answers every search with one line and a best move only once it is stopped
+        let mut engine = script(
+            "stopped-search",
+            "n=0\n\
+             while read line; do\n\
+             case \"$line\" in\n\
+             go*) n=$((n+1)); echo \"info depth $n score mate $n pv e2e4\";;\n\
+             stop) echo \"bestmove e2e4\";;\n\
+             esac\n\
+             done\n",
+        );
+        engine.analyse(START_FEN, &[]);
+        engine.analyse(START_FEN, &["e2e4".to_string()]);
+        engine.stop();
+        engine.go(START_FEN, &[], Duration::from_millis(10));
+        engine.stop();
+        engine.analyse(START_FEN, &[]);
+
+        // Only the last search is reported on
+        let start = Instant::now();
+        let mut infos = Vec::new();
+        while start.elapsed() < Duration::from_millis(500) {
+            for event in engine.poll() {
+                match event {
+                    Event::Info(info) => infos.push(info),
+                    _ => panic!("Abandoned searches report nothing"),
+                }
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(infos.len(), 1);
+        assert!(infos[0].depth == 4 && infos[0].score == Score::Mate(4));
+    }
+
+    #[test]
+    fn info_lines() {
+        let info = Info::parse("info depth 20 seldepth 28 multipv 2 score cp -35 upperbound nodes 1 pv g8f6 c2c4").unwrap();
+        assert!(info.depth == 20 && info.multipv == 2 && info.score == Score::Centipawns(-35));
+        assert_eq!(info.pv, ["g8f6", "c2c4"]);
+        assert!(Info::parse("info string NNUE enabled").is_none());
+        assert!(Info::parse("info depth 3 currmove e2e4 currmovenumber 1").is_none());
+        assert_eq!(Score::Mate(2).for_white(Colour::Black).to_string(), "#-2");
+        assert_eq!(Score::Centipawns(35).to_string(), "+0.35");
+    }
+
+    #[test]
+    fn uci_moves() {
+        assert_eq!(uci_to_move("e2e4"), Some(((6, 4), (4, 4), None)));
+        assert_eq!(uci_to_move("a7a8q"), Some(((1, 0), (0, 0), Some('q'))));
+        assert_eq!(uci_to_move("0000"), None);
+        assert_eq!(uci_to_move("e7e8k"), None);
+    }
+}
@@ -0,0 +1,257 @@
+use super::*;
+use san::SanMove;
+
+/// ## Player
+/// A player of a match, given on the command line as a level of the built-in computer or the path of a UCI engine.
+pub enum Player {
+    Engine(path::PathBuf),
+    Computer(ai::Level),
+}
+
+impl Player {
+    /// Reads a player: the name of a level, or else the path of an engine binary.
+    pub fn parse(arg: &str) -> Player {
+        match ai::LEVELS.iter().find(|level| level.name.eq_ignore_ascii_case(arg)) {
+            Some(level) => Player::Computer(*level),
+            None => Player::Engine(path::PathBuf::from(arg)),
+        }
+    }
+}
+
+/// A player once the match has started, with its search for the next move if it is searching.
+enum Contestant {
+    Engine(uci::Engine),
+    Computer(ai::Level, Option<ai::Search>),
+}
+
+impl Contestant {
+    fn name(&self) -> String {
+        match self {
+            Contestant::Engine(engine) => engine.name.clone(),
+            Contestant::Computer(level, _search) => format!("Computer ({})", level.name),
+        }
+    }
+
+    /// Starts searching the position reached by playing the moves, given in UCI notation, from the start FEN.
+    fn go(&mut self, start_fen: &str, moves: &[String], fen: &str) {
+        match self {
+            Contestant::Engine(engine) => engine.go(start_fen, moves, ENGINE_MOVE_TIME),
+            Contestant::Computer(level, search) => *search = Some(ai::Search::start(fen.to_string(), *level)),
+        }
+    }
+
+    /// The move found once the search is finished, or why none was found.
+    fn poll(&mut self) -> Option<Result<SanMove, String>> {
+        match self {
+            Contestant::Computer(_level, search) => {
+                let found = search.as_ref()?.poll()?;
+                *search = None;
+                Some(Ok(found))
+            }
+            Contestant::Engine(engine) => {
+                let name = engine.name.clone();
+                engine.poll().into_iter().find_map(|event| match event {
+                    uci::Event::BestMove(bestmove) => {
+                        Some(uci::uci_to_move(&bestmove).ok_or(format!("{} found no move ({})", name, bestmove)))
+                    }
+                    uci::Event::Crashed => Some(Err(format!("{} crashed", name))),
+                    uci::Event::TimedOut => Some(Err(format!("{} stopped answering", name))),
+                    uci::Event::Info(_info) => None,
+                })
+            }
+        }
+    }
+}
+
+/// ## Match
+/// Games between two players, who swap colours after every game.
+/// The game being played is the history of the application, the match only finds the moves and keeps the score.
+pub struct Match {
+    players: [Contestant; 2],
+    games: usize, // Number of games to play
+    finished: Vec<(String, usize, &'static str)>, // PGN, index of the player with white and result of every finished game
+    searching: Option<usize>, // Ply of the main line being searched
+    pub path: path::PathBuf, // PGN file the games and the score table are written to
+}
+
+impl Match {
+    /// Starts the engines of a match of the given number of games.
+    pub fn start(players: [Player; 2], games: usize, path: path::PathBuf) -> Result<Match, String> {
+        let [first, second] = players;
+        Ok(Match {
+            players: [Match::contestant(first)?, Match::contestant(second)?],
+            games,
+            finished: Vec::new(),
+            searching: None,
+            path,
+        })
+    }
+
+    fn contestant(player: Player) -> Result<Contestant, String> {
+        match player {
+            Player::Engine(path) => Ok(Contestant::Engine(uci::Engine::start(&path)?)),
+            Player::Computer(level) => Ok(Contestant::Computer(level, None)),
+        }
+    }
+
+    /// Index of the player with white in the game being played. The first player has white in the first game.
+    fn white(&self) -> usize {
+        self.finished.len() % 2
+    }
+
+    /// Names of the players with white and black in the game being played.
+    pub fn names(&self) -> (String, String) {
+        let white = self.white();
+        (self.players[white].name(), self.players[1 - white].name())
+    }
+
+    /// Number of the game being played, starting at 1.
+    pub fn round(&self) -> usize {
+        self.finished.len() + 1
+    }
+
+    /// Number of games in the match.
+    pub fn games(&self) -> usize {
+        self.games
+    }
+
+    /// Asks the player to move for a move at the end of the main line, starting its search if it hasn't yet.
+    /// Returns the move once it is found, or why none was found.
+    pub fn think(&mut self, history: &MoveTree) -> Option<Result<SanMove, String>> {
+        let main_line = history.main_line();
+        let fen = main_line.last().map_or(history.start_fen.as_str(), |node| node.fen.as_str());
+        let mover = match game_from_fen(fen).current_turn {
+            Colour::White => self.white(),
+            Colour::Black => 1 - self.white(),
+        };
+        if self.searching != Some(main_line.len()) {
+            let moves: Vec<String> = main_line.iter().map(|node| uci::move_to_uci(&node.record)).collect();
+            self.players[mover].go(&history.start_fen, &moves, fen);
+            self.searching = Some(main_line.len());
+        }
+        self.players[mover].poll()
+    }
+
+    /// Records the result of the game being played. Returns whether the match is over.
+    pub fn finish_game(&mut self, history: &MoveTree, result: &'static str) -> bool {
+        let (white, black) = self.names();
+        let tags = [
+            ("Event", "Engine match".to_string()),
+            ("Round", self.round().to_string()),
+            ("White", white),
+            ("Black", black),
+        ];
+        self.finished.push((pgn::write_with_variations(history, result, &tags), self.white(), result));
+        self.searching = None;
+        self.finished.len() >= self.games
+    }
+
+    /// Points, wins, draws and losses of a player in the finished games.
+    fn score(&self, player: usize) -> (f32, usize, usize, usize) {
+        let (mut wins, mut draws, mut losses) = (0, 0, 0);
+        for (_pgn, white, result) in &self.finished {
+            match (*result, *white == player) {
+                ("1-0", true) | ("0-1", false) => wins += 1,
+                ("0-1", true) | ("1-0", false) => losses += 1,
+                _ => draws += 1,
+            }
+        }
+        (wins as f32 + draws as f32 / 2.0, wins, draws, losses)
+    }
+
+    /// The score of the match in short, e.g. "Stockfish 2.5 - 1.5 Computer (Hard)".
+    pub fn summary(&self) -> String {
+        format!(
+            "{} {} - {} {}",
+            self.players[0].name(),
+            self.score(0).0,
+            self.score(1).0,
+            self.players[1].name()
+        )
+    }
+
+    /// Writes the finished games to the PGN file, preceded by the score table.
+    /// The table is written on escaped lines, which PGN readers skip.
+    pub fn save(&self) -> Result<(), String> {
+        let width = self.players.iter().map(|player| player.name().len()).max().unwrap_or(0).max(6);
+        let mut text = format!("% Engine match, {} of {} games played\n", self.finished.len(), self.games);
+        text.push_str(&format!("% {:width$}  Score  Wins  Draws  Losses\n", "Player", width = width));
+        for (i, player) in self.players.iter().enumerate() {
+            let (points, wins, draws, losses) = self.score(i);
+            text.push_str(&format!(
+                "% {:width$}  {:>5}  {:>4}  {:>5}  {:>6}\n",
+                player.name(),
+                points,
+                wins,
+                draws,
+                losses,
+                width = width
+            ));
+        }
+        for (pgn, _white, _result) in &self.finished {
+            text.push('\n');
+            text.push_str(pgn);
+        }
+        fs::write(&self.path, text).map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+}
+
+/// The result of the game at the end of the main line if it is over, see pgn::result.
+/// Threefold repetition and the fifty move rule end every game, so no game is cut short by a move limit.
+pub fn game_result(history: &MoveTree) -> Option<&'static str> {
+    match pgn::result(history) {
+        "*" => None,
+        result => Some(result),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkmate() {
+        let history = pgn::read("1. f3 e5 2. g4 Qh4# 0-1").unwrap();
+        assert_eq!(game_result(&history), Some("0-1"));
+    }
+
+    #[test]
+    fn stalemate() {
+        let history = pgn::read("[FEN \"k7/8/8/8/8/8/1Q6/K7 w - - 0 1\"]\n\n1. Qb6 *").unwrap();
+        assert_eq!(game_result(&history), Some("1/2-1/2"));
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let twice = pgn::read("1. Nf3 Nf6 2. Ng1 Ng8 3. Nf3 Nf6 4. Ng1 *").unwrap();
+        assert_eq!(game_result(&twice), None);
+        let thrice = pgn::read("1. Nf3 Nf6 2. Ng1 Ng8 3. Nf3 Nf6 4. Ng1 Ng8 *").unwrap();
+        assert_eq!(game_result(&thrice), Some("1/2-1/2"));
+    }
+
+    #[test]
+    fn fifty_moves() {
+        let history = pgn::read("[FEN \"4k3/8/8/8/8/8/8/R3K3 w - - 98 80\"]\n\n80. Ra2 *").unwrap();
+        assert_eq!(game_result(&history), None);
+        let history = pgn::read("[FEN \"4k3/8/8/8/8/8/8/R3K3 w - - 98 80\"]\n\n80. Ra2 Kd8 *").unwrap();
+        assert_eq!(game_result(&history), Some("1/2-1/2"));
+        // A pawn move starts the count again
+        let history = pgn::read("[FEN \"4k3/8/8/8/8/8/P7/R3K3 w - - 99 80\"]\n\n80. a3 *").unwrap();
+        assert_eq!(game_result(&history), None);
+    }
+
+    #[test]
+    fn score_and_summary() {
+        let players = [Player::Computer(ai::LEVELS[0]), Player::Computer(ai::LEVELS[3])];
+        let mut versus = Match::start(players, 4, path::PathBuf::from("match.pgn")).unwrap();
+        assert_eq!(versus.summary(), "Computer (Beginner) 0 - 0 Computer (Hard)");
+
+        // The first player has white in the first and third games
+        for (white, result) in [(0, "1-0"), (1, "1-0"), (0, "1/2-1/2"), (1, "0-1")].iter() {
+            versus.finished.push((String::new(), *white, *result));
+        }
+        assert_eq!(versus.score(0), (2.5, 2, 1, 1));
+        assert_eq!(versus.score(1), (1.5, 1, 1, 2));
+        assert_eq!(versus.summary(), "Computer (Beginner) 2.5 - 1.5 Computer (Hard)");
+    }
+}